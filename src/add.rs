@@ -1,145 +1,217 @@
 use std::{
-    fs::{self, create_dir_all, symlink_metadata},
-    io::{ErrorKind, Write as _, stdin, stdout},
-    os::unix::fs::symlink,
-    path::Path,
-    process::exit,
+    fs::symlink_metadata,
+    io::ErrorKind,
+    path::{Path, PathBuf},
 };
 
-use anyhow::{Context as _, Result, ensure};
+use anyhow::{Context as _, Result, bail};
+
+use crate::{
+    backups::backup,
+    context::Context,
+    diff::print_diff,
+    hooks::{HookCommand, with_hooks},
+    link,
+    ops::{self, dry_run},
+    output::Kind,
+    prompt::Prompt,
+    source::{self, Source},
+    util::{ConfigPath, resolve_config_path, system_path},
+};
 
-use crate::util::{config_path, paths_equal, rerun_with_root, system_path};
+/// What `add` did
+pub struct Added {
+    pub config_path: PathBuf,
+    pub system_path: PathBuf,
+    /// The sub-dir of files/ the config path is in
+    pub layer: String,
+    pub kind: Kind,
+    /// Whether the system path already was what `add` would have made it
+    pub unchanged: bool,
+    /// The id of the backup of the overwritten system path, if there was one
+    pub backup: Option<String>,
+}
 
-/// Symlink a the given path to its location in the actual system
-pub fn add(path: &Path, force: bool, copy: bool) -> Result<()> {
-    if copy {
-        return add_copy(path, force);
+/// Symlink a the given path to its location in the actual system.
+/// The symlink is relative if `relative` or `relative_symlinks` in the config is set.
+pub fn add(
+    context: &Context,
+    path: &Path,
+    force: bool,
+    copy: bool,
+    relative: bool,
+) -> Result<Added> {
+    with_hooks(&context.config, HookCommand::Add, path, || {
+        context.escalating(|| add_path(context, path, force, copy, relative))
+    })
+}
+
+fn add_path(
+    context: &Context,
+    path: &Path,
+    force: bool,
+    copy: bool,
+    relative: bool,
+) -> Result<Added> {
+    let ConfigPath {
+        path: config_path,
+        layer,
+    } = resolve_config_path(&context.config, path)?;
+
+    // Generated paths (like templates) cant be symlinked
+    if copy || source::generated_from(&config_path).is_some() {
+        return add_copy(context, path, force);
     }
+    let system_path = system_path(&context.config, path)?;
+
+    let mut added = Added {
+        config_path,
+        system_path,
+        layer,
+        kind: Kind::Symlink,
+        unchanged: false,
+        backup: None,
+    };
 
-    let config_path = config_path(path)?;
-    let system_path = system_path(path)?;
+    let relative = relative || context.config.relative_symlinks;
 
     // If the system path already exists
-    if symlink_metadata(&system_path).is_ok() {
+    if symlink_metadata(&added.system_path).is_ok() {
         // Check if it is a symlink that points to the correct location
-        if let Ok(destination) = fs::read_link(&system_path)
-            && destination == config_path
-        {
-            return Ok(());
+        if link::points_to(&added.system_path, &added.config_path, relative) {
+            added.unchanged = true;
+            return Ok(added);
         }
 
-        // -> It isnt
-        ask_for_overwrite(force, &config_path, &system_path)?;
+        // Symlinks that only differ in being relative or absolute are just replaced
+        if link::target(&added.system_path).is_ok_and(|target| target == added.config_path) {
+            ops::remove(&added.system_path)?;
+        } else {
+            added.backup = Some(ask_for_overwrite(
+                &*context.prompt,
+                force,
+                &Source::plain(&added.config_path),
+                &added.system_path,
+            )?);
+        }
     }
 
     // At this point the path either doesn't exist yet, or the user has decided to overwrite it
-    println!(
-        "Symlinking {} to {}",
-        config_path.display(),
-        system_path.display(),
-    );
-    create_symlink(&config_path, &system_path)
-}
+    if !dry_run() {
+        println!(
+            "Symlinking {} to {} (layer {})",
+            added.config_path.display(),
+            added.system_path.display(),
+            added.layer,
+        );
+    }
+    create_symlink(&added.config_path, &added.system_path, relative)?;
 
-/// Symlink a the given path to its location in the actual system
-pub fn add_copy(path: &Path, force: bool) -> Result<()> {
-    let config_path = config_path(path)?;
-    let system_path = system_path(path)?;
+    Ok(added)
+}
 
-    ensure!(
-        !config_path.is_dir(),
-        "Only files and symlinks are currently supported with --copy"
-    );
+/// Copy the given path (rendering it if it is a template) to its location in the actual system
+fn add_copy(context: &Context, path: &Path, force: bool) -> Result<Added> {
+    let ConfigPath {
+        path: config_path,
+        layer,
+    } = resolve_config_path(&context.config, path)?;
+    let system_path = system_path(&context.config, path)?;
+
+    let source = Source::new(context, &config_path)?;
+
+    let mut added = Added {
+        config_path,
+        system_path,
+        layer,
+        kind: Kind::Copy,
+        unchanged: false,
+        backup: None,
+    };
 
     // If path exists on the system
-    if fs::exists(path).with_context(
-        || format!("checking if the path {} already exists", path.display()),
-        // And is not equal to the one in the config
-    )? && let Err(e) = paths_equal(&config_path, &system_path)
-    {
-        eprintln!("{e}");
-        ask_for_overwrite(force, &config_path, &system_path)?;
+    if symlink_metadata(&added.system_path).is_ok() {
+        // Check if it is equal to the one in the config
+        if source.equals(&added.system_path).is_ok() {
+            added.unchanged = true;
+            return Ok(added);
+        }
+
+        added.backup = Some(ask_for_overwrite(
+            &*context.prompt,
+            force,
+            &source,
+            &added.system_path,
+        )?);
     }
 
     // At this point the path either doesn't exist yet, or the user has decided to overwrite it
-    println!(
-        "Copying {} to {}",
-        config_path.display(),
-        system_path.display(),
-    );
-
-    fs::copy(config_path, system_path).map(|_| {}).context(
-        // Ignore number of bytes copied
-        "copying config path to system path",
+    if !dry_run() {
+        println!(
+            "Copying {} to {} (layer {})",
+            source.origin.display(),
+            added.system_path.display(),
+            added.layer,
+        );
+    }
+
+    ops::create_dir_all(
+        added
+            .system_path
+            .parent()
+            .context("Failed to get parent of system path")?,
     )
+    .context("creating parent directories")?;
+
+    ops::copy(&source.path, &added.system_path, false)
+        .context("copying config path to system path")?;
+
+    Ok(added)
 }
 
-/// Asks for overwrite and removes the path from the system if requested, exits if not
-fn ask_for_overwrite(force: bool, config_path: &Path, system_path: &Path) -> Result<()> {
+/// Asks for overwrite and moves the path from the system into the backup store if requested, returning the id of the backup.
+/// Fails if the user declines.
+pub fn ask_for_overwrite(
+    prompt: &dyn Prompt,
+    force: bool,
+    source: &Source,
+    system_path: &Path,
+) -> Result<String> {
     if force
-        || match paths_equal(config_path, system_path) {
+        || match source.equals(system_path) {
             Ok(()) => true,
             Err(e) => {
-                bool_question(&format!("{e}, overwrite system path?")).unwrap_or_default()
-                    && bool_question("Are you sure?").unwrap_or_default()
+                if let Err(diff_error) = print_diff(source, system_path) {
+                    eprintln!("Failed to print diff: {diff_error:#}");
+                }
+
+                prompt.bool_question(&format!("{e}, overwrite system path?"), false)?
+                    && prompt.bool_question("Are you sure?", false)?
             }
         }
     {
-        if system_path.is_dir() {
-            fs::remove_dir_all(system_path)
-        } else {
-            fs::remove_file(system_path)
-        }
-        .with_context(|| format!("removing path {}", system_path.display()))
+        backup(system_path)
     } else {
-        exit(1)
+        bail!("Not overwriting {}", system_path.display())
     }
 }
 
 /// Creates a symlink from `config_path` to `system_path`
-#[expect(clippy::wildcard_enum_match_arm)]
-fn create_symlink(config_path: &Path, system_path: &Path) -> Result<()> {
+fn create_symlink(config_path: &Path, system_path: &Path, relative: bool) -> Result<()> {
     // Try creating the symlink
-    if let Err(e) = symlink(config_path, system_path) {
-        match e.kind() {
-            ErrorKind::PermissionDenied => {
-                rerun_with_root("Creating symlink");
-            }
-            ErrorKind::NotFound => {
-                create_dir_all(
-                    system_path
-                        .parent()
-                        .context("Failed to get parent of system path")?,
-                )
-                .context("creating parent directories")?;
-
-                create_symlink(config_path, system_path)?;
-            }
-            _ => {
-                return Err(e.into());
-            }
+    if let Err(e) = link::symlink(config_path, system_path, relative) {
+        if e.kind() == ErrorKind::NotFound {
+            ops::create_dir_all(
+                system_path
+                    .parent()
+                    .context("Failed to get parent of system path")?,
+            )
+            .context("creating parent directories")?;
+
+            create_symlink(config_path, system_path, relative)?;
+        } else {
+            return Err(e).with_context(|| format!("creating symlink {}", system_path.display()));
         }
     }
     Ok(())
 }
-
-/// Asks the user the given question and returns the users answer.
-pub fn bool_question(question: &str) -> Result<bool> {
-    print!("{question} ");
-
-    stdout().flush()?;
-
-    let mut buffer = String::with_capacity(3); // The longest accepted answer is 3 characters long
-
-    loop {
-        buffer.clear();
-
-        stdin().read_line(&mut buffer)?;
-
-        match buffer.trim() {
-            "y" | "Y" | "yes" | "Yes" => return Ok(true),
-            "n" | "N" | "no" | "No" => return Ok(false),
-            _other => {}
-        }
-    }
-}
@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+
+use anyhow::{Context as _, Result};
+
+use crate::{
+    SILENT,
+    add::add,
+    batch::{check_failed, try_all},
+    context::Context,
+    list, manifest,
+    status::{State, state},
+    util::{config_path, system_path},
+};
+
+/// Adds every path in the manifest to the system and reports managed paths that aren't in it
+pub fn apply(context: &Context, force: bool) -> Result<()> {
+    let entries = manifest::load(&context.config)?;
+    let paths: Vec<_> = entries.iter().map(|entry| entry.path.clone()).collect();
+
+    let system_paths = entries
+        .iter()
+        .map(|entry| system_path(&context.config, &entry.path))
+        .collect::<Result<HashSet<_>>>()?;
+
+    let (mut created, mut repaired, mut unchanged) = (0_usize, 0_usize, 0_usize);
+
+    // A single failing path (like a declined overwrite) doesn't stop the others
+    let failed = try_all(&paths, |path| {
+        let entry = entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .context("Manifest entry went missing")?;
+        let config_path = config_path(&context.config, &entry.path)?;
+        let system_path = system_path(&context.config, &entry.path)?;
+
+        let state = state(context, &config_path, &system_path, entry.copy)?;
+        if state.in_sync() {
+            unchanged = unchanged.saturating_add(1);
+            return Ok(());
+        }
+
+        add(context, &entry.path, force, entry.copy, entry.relative)?;
+
+        // Only count the path once it was actually added
+        let counter = if matches!(state, State::Missing) {
+            &mut created
+        } else {
+            &mut repaired
+        };
+        *counter = counter.saturating_add(1);
+
+        Ok(())
+    })?;
+
+    // Report symlinks that were made by dots, but aren't in the manifest (anymore)
+    let mut extra = 0_usize;
+    for managed in list::managed(&context.config)? {
+        if !system_paths.contains(&managed.system_path) {
+            println!("Extra: {}", managed.path);
+            extra = extra.saturating_add(1);
+        }
+    }
+
+    if !SILENT.get().is_some_and(|silent| *silent) {
+        println!(
+            "{created} created, {repaired} repaired, {unchanged} unchanged, {extra} extra, {} failed",
+            failed.len()
+        );
+    }
+
+    check_failed(&failed, paths.len())
+}
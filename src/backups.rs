@@ -0,0 +1,295 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write as _},
+    os::unix::fs::{MetadataExt as _, chown},
+    path::{self, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context as _, Result, bail, ensure};
+
+use crate::{
+    SILENT,
+    add::ask_for_overwrite,
+    copy::{copy_tree, report},
+    ops::{self, dry_run},
+    prompt::Prompt,
+    source::Source,
+    util::{home, is_root, xdg_dir},
+};
+
+/// A path that was moved into the backup store
+struct Backup {
+    id: String,
+    /// Seconds since the unix epoch
+    time: u64,
+    /// The command that replaced the path
+    command: String,
+    /// Where the path was on the system
+    original_path: PathBuf,
+}
+impl Backup {
+    /// The location of the backed up path in the store
+    fn path(&self, store: &Path) -> Result<PathBuf> {
+        Ok(store.join(&self.id).join(
+            self.original_path
+                .file_name()
+                .context("Backed up path has no file name")?,
+        ))
+    }
+    /// The line representing the backup in the index
+    fn index_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.id,
+            self.time,
+            self.command,
+            self.original_path.display()
+        )
+    }
+}
+
+/// Prints all backups
+pub fn list() -> Result<()> {
+    for backup in load()? {
+        println!(
+            "{}  {}  {}  ({})",
+            backup.id,
+            format_time(backup.time),
+            backup.original_path.display(),
+            backup.command
+        );
+    }
+
+    Ok(())
+}
+
+/// The directory backups are stored in
+pub fn store() -> Result<PathBuf> {
+    Ok(xdg_dir("XDG_STATE_HOME", ".local/state")?.join("dots/backups"))
+}
+
+/// Moves the path into the backup store, returning the id of the backup
+pub fn backup(path: &Path) -> Result<String> {
+    if dry_run() {
+        println!("Would back up and remove {}", path.display());
+        return Ok(String::new());
+    }
+
+    let original_path =
+        path::absolute(path).with_context(|| format!("making {} absolute", path.display()))?;
+    ensure!(
+        !original_path
+            .to_str()
+            .context("Failed to convert path to string")?
+            .contains('\n'),
+        "Paths containing newlines cant be backed up"
+    );
+
+    let store = store()?;
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("System time is before the unix epoch")?
+        .as_secs();
+
+    // Find an unused id
+    let (id, dir) = (0..u32::MAX)
+        .map(|counter| {
+            let id = format!("{time}-{counter}");
+            let dir = store.join(&id);
+            (id, dir)
+        })
+        .find(|candidate| fs::symlink_metadata(&candidate.1).is_err())
+        .context("Failed to find an unused backup id")?;
+
+    let created: Vec<_> = dir
+        .ancestors()
+        .take_while(|ancestor| fs::symlink_metadata(ancestor).is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("creating backup directory {}", dir.display()))?;
+
+    let backup = Backup {
+        id,
+        time,
+        command: env::args()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace(['\t', '\n'], " "),
+        original_path,
+    };
+
+    move_path(path, &backup.path(&store)?)?;
+
+    // Record the backup in the index
+    let index_path = store.join("index");
+    let mut index = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&index_path)
+        .context("opening backup index")?;
+    index
+        .write_all(backup.index_line().as_bytes())
+        .context("writing backup index")?;
+
+    give_to_home_owner(created.iter().chain([&index_path]))?;
+
+    if !SILENT.get().is_some_and(|silent| *silent) {
+        println!("Backed up {} as {}", path.display(), backup.id);
+    }
+
+    Ok(backup.id)
+}
+
+/// Copies the backup with the given id back to its original location
+pub fn restore(prompt: &dyn Prompt, id: &str, force: bool) -> Result<()> {
+    let store = store()?;
+
+    let Some(backup) = load()?.into_iter().find(|backup| backup.id == id) else {
+        bail!("No backup with id {id}, see `dots backups list`");
+    };
+    let backup_path = backup.path(&store)?;
+
+    // If something is in the way, handle it like `add` does
+    if fs::symlink_metadata(&backup.original_path).is_ok() {
+        ask_for_overwrite(
+            prompt,
+            force,
+            &Source::plain(&backup_path),
+            &backup.original_path,
+        )?;
+    }
+
+    if !dry_run() {
+        println!(
+            "Restoring {} to {}",
+            backup_path.display(),
+            backup.original_path.display()
+        );
+    }
+
+    ops::create_dir_all(
+        backup
+            .original_path
+            .parent()
+            .context("Failed to get parent of original path")?,
+    )?;
+    ops::copy(&backup_path, &backup.original_path, is_root()?)
+}
+
+/// Moves the backup with the given id back to its original location and removes it from the store
+pub fn undo_backup(id: &str) -> Result<()> {
+    if dry_run() {
+        return Ok(());
+    }
+
+    let store = store()?;
+    let backups = load()?;
+
+    let backup = backups
+        .iter()
+        .find(|backup| backup.id == id)
+        .with_context(|| format!("No backup with id {id}"))?;
+
+    move_path(&backup.path(&store)?, &backup.original_path)?;
+    fs::remove_dir(store.join(id)).context("removing backup directory")?;
+
+    // Remove the backup from the index
+    let index: String = backups
+        .iter()
+        .filter(|backup| backup.id != id)
+        .map(Backup::index_line)
+        .collect();
+    fs::write(store.join("index"), index).context("writing backup index")
+}
+
+/// Loads all backups from the index
+fn load() -> Result<Vec<Backup>> {
+    let path = store()?.join("index");
+
+    let string = match fs::read_to_string(&path) {
+        Ok(string) => string,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+
+    string
+        .lines()
+        .map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let mut field = || fields.next().context("Missing field in backup index");
+
+            Ok(Backup {
+                id: field()?.to_owned(),
+                time: field()?.parse().context("Invalid time in backup index")?,
+                command: field()?.to_owned(),
+                original_path: field()?.into(),
+            })
+        })
+        .collect()
+}
+
+/// Makes the paths owned by the owner of the home directory when running as root,
+/// so the store stays usable by the user after dots was rerun with root privileges.
+/// The backed up paths themselves keep their owner.
+fn give_to_home_owner<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> Result<()> {
+    if !is_root()? {
+        return Ok(());
+    }
+
+    let home = home()?;
+    let owner = fs::metadata(&home).with_context(|| format!("getting owner of {home}"))?;
+
+    for path in paths {
+        chown(path, Some(owner.uid()), Some(owner.gid()))
+            .with_context(|| format!("changing owner of {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Moves the path, falling back to copying and removing it if it is on another filesystem
+fn move_path(source: &Path, target: &Path) -> Result<()> {
+    match ops::rename(source, target) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            report(&copy_tree(source, target, is_root()?)?);
+            ops::remove(source)
+        }
+        Err(e) => {
+            Err(e).with_context(|| format!("moving {} to {}", source.display(), target.display()))
+        }
+    }
+}
+
+/// Formats the seconds since the unix epoch as a UTC date and time
+#[expect(clippy::arithmetic_side_effects, clippy::integer_division)] // Cant overflow for any realistic time
+fn format_time(seconds: u64) -> String {
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+
+    // Convert days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
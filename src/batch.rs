@@ -0,0 +1,259 @@
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fs,
+    io::{self, Read as _},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{Context as _, Result, bail};
+use glob::glob;
+
+use crate::{
+    SILENT,
+    config::Config,
+    context::requires_root,
+    placeholders::{collapse_home, collapse_subdir, expand_with},
+    source,
+    util::system_path,
+};
+
+/// The paths that were already handled before rerunning with root privileges, which `run_all()` skips.
+/// Set by the `dots` binary from the arguments added by `done_args()`.
+pub static SKIP: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// The paths `run_all()` handled successfully in this process
+static DONE: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// The arguments that tell a rerun of the command which paths are already done, so they aren't handled twice
+pub fn done_args() -> Vec<OsString> {
+    let done = DONE.lock().map(|done| done.clone()).unwrap_or_default();
+
+    SKIP.get()
+        .into_iter()
+        .flatten()
+        .chain(&done)
+        .flat_map(|path| [OsString::from("--done"), path.into()])
+        .collect()
+}
+
+/// Where glob patterns are matched
+#[derive(Clone, Copy)]
+pub enum Against {
+    /// The files/ directory (for paths that are added or removed)
+    Files,
+    /// The system (for paths that are imported)
+    System,
+}
+
+/// Collects the given paths and the ones listed in `from_file` (`-` for stdin, one per line),
+/// replacing glob patterns with the paths they match
+pub fn paths(
+    config: &Config,
+    paths: Vec<PathBuf>,
+    from_file: Option<&Path>,
+    against: Against,
+) -> Result<Vec<PathBuf>> {
+    let mut collected = Vec::new();
+
+    let listed = match from_file {
+        Some(from_file) => read_list(from_file)?,
+        None => Vec::new(),
+    };
+
+    for path in paths.into_iter().chain(listed) {
+        let string = path.to_str().context("Failed to convert path to string")?;
+
+        if string.contains(['*', '?', '[']) {
+            let matches = match against {
+                Against::Files => matches_in_files(config, string)?,
+                Against::System => matches_on_system(config, string)?,
+            };
+            if matches.is_empty() {
+                bail!("No paths match {string}");
+            }
+            collected.extend(matches.into_iter().map(PathBuf::from));
+        } else {
+            collected.push(path);
+        }
+    }
+
+    Ok(collected)
+}
+
+/// Reads the paths in the file (`-` for stdin), skipping empty lines and comments
+fn read_list(path: &Path) -> Result<Vec<PathBuf>> {
+    let contents = if path == Path::new("-") {
+        let mut contents = String::new();
+        io::stdin()
+            .read_to_string(&mut contents)
+            .context("reading paths from stdin")?;
+        contents
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("reading paths from {}", path.display()))?
+    };
+
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// The paths in files/ matching the pattern, in the format taken by `add`
+fn matches_in_files(config: &Config, pattern: &str) -> Result<BTreeSet<String>> {
+    let expanded = expand_with(pattern, &config.vars)?;
+    let files_path = Path::new(&config.files_path);
+
+    // Elided patterns are matched in every layer
+    let patterns = match expanded.strip_prefix('/') {
+        Some(relative) => config
+            .layers()?
+            .into_iter()
+            .map(|layer| files_path.join(layer).join(relative))
+            .collect(),
+        None => vec![files_path.join(&expanded)],
+    };
+
+    let mut matches = BTreeSet::new();
+    for pattern in patterns {
+        let pattern = pattern
+            .to_str()
+            .context("Failed to convert path to string")?;
+
+        for path in glob(pattern).with_context(|| format!("parsing pattern {pattern}"))? {
+            let path = source::without_suffix(&path?);
+            let relative = path
+                .strip_prefix(files_path)
+                .context("Matched path isn't in files/")?;
+
+            let mut components = relative.components();
+            let subdir = components.next().context("Path is empty")?.as_os_str();
+            let rest = collapse_home(components.as_path())?;
+            let rest = rest.to_str().context("Failed to convert path to string")?;
+
+            // Elided patterns stay elided, so they resolve to the highest priority layer
+            matches.insert(if expanded.starts_with('/') {
+                format!("/{rest}")
+            } else {
+                format!(
+                    "{}/{rest}",
+                    collapse_subdir(
+                        subdir
+                            .to_str()
+                            .context("Failed to convert subdir to string")?
+                    )
+                )
+            });
+        }
+    }
+
+    Ok(matches)
+}
+
+/// The paths on the system matching the pattern, in the format taken by `add`
+fn matches_on_system(config: &Config, pattern: &str) -> Result<BTreeSet<String>> {
+    let system_pattern = system_path(config, Path::new(pattern))?;
+    let system_pattern = system_pattern
+        .to_str()
+        .context("Failed to convert path to string")?;
+
+    // The sub-dir in front of the system path, if it isn't elided
+    let expanded = expand_with(pattern, &config.vars)?;
+    let subdir = expanded
+        .strip_suffix(system_pattern)
+        .context("Failed to get sub-dir of pattern")?;
+
+    let mut matches = BTreeSet::new();
+    for path in glob(system_pattern).with_context(|| format!("parsing pattern {pattern}"))? {
+        let path = collapse_home(&path?)?;
+        let path = path.to_str().context("Failed to convert path to string")?;
+
+        matches.insert(format!("{subdir}{path}"));
+    }
+
+    Ok(matches)
+}
+
+/// Runs the action on every path collected by `paths()`, see `run_all()`
+pub fn run(
+    config: &Config,
+    paths: Vec<PathBuf>,
+    from_file: Option<&Path>,
+    against: Against,
+    action: impl Fn(&Path) -> Result<()>,
+) -> Result<()> {
+    run_all(&self::paths(config, paths, from_file, against)?, action)
+}
+
+/// Runs the action on every path, reporting failures instead of stopping at the first one.
+/// Fails if any of the paths failed.
+pub fn run_all(paths: &[PathBuf], mut action: impl FnMut(&Path) -> Result<()>) -> Result<()> {
+    // Single paths fail just like before
+    if let [ref path] = *paths
+        && !skipped(path)
+    {
+        return action(path);
+    }
+
+    let failed = try_all(paths, action)?;
+
+    if failed.is_empty() && !SILENT.get().is_some_and(|silent| *silent) {
+        println!("All {} paths succeeded", paths.len());
+    }
+
+    check_failed(&failed, paths.len())
+}
+
+/// Runs the action on every path, printing the errors of the paths that fail and returning them.
+///
+/// Paths that were already done before rerunning with root privileges are skipped.
+/// Errors that require root privileges are returned right away, as rerunning with them has to happen for the whole command.
+pub fn try_all(
+    paths: &[PathBuf],
+    mut action: impl FnMut(&Path) -> Result<()>,
+) -> Result<Vec<&Path>> {
+    let mut failed = Vec::new();
+
+    for path in paths.iter().filter(|path| !skipped(path)) {
+        match action(path) {
+            Ok(()) => {
+                if let Ok(mut done) = DONE.lock() {
+                    done.push(path.clone());
+                }
+            }
+            Err(error) if requires_root(&error) => return Err(error),
+            Err(error) => {
+                eprintln!("{}: {error:#}", path.display());
+                failed.push(path.as_path());
+            }
+        }
+    }
+
+    Ok(failed)
+}
+
+/// Fails listing the paths, if any of the `total` paths failed
+pub fn check_failed(failed: &[&Path], total: usize) -> Result<()> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    bail!(
+        "{} of {total} paths failed:\n{}",
+        failed.len(),
+        failed
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    )
+}
+
+/// Whether the path was already done before rerunning with root privileges
+fn skipped(path: &Path) -> bool {
+    SKIP.get()
+        .is_some_and(|skip| skip.iter().any(|skipped| skipped == path))
+}
@@ -1,72 +1,205 @@
-use std::{fs, io::stdin, path::PathBuf, sync::LazyLock};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use anyhow::{Context as _, Result, bail, ensure};
+use serde::{Deserialize, Serialize};
 
-use crate::{add::bool_question, util::home};
+use crate::{
+    escalate::{Backend, Mode},
+    hooks::Hook,
+    ops::{self, dry_run},
+    placeholders::expand_with,
+    prompt::Prompt,
+    util::{get_hostname, xdg_dir},
+};
 
-#[expect(clippy::unwrap_used)]
-pub static CONFIG: LazyLock<Config> = LazyLock::new(|| Config::load().unwrap());
+/// The config path passed with --config
+pub static CONFIG_PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
 
-#[derive(Default)]
+/// The location of the config file.
+///
+/// In order of precedence: --config, `$DOTS_CONFIG`, `$XDG_CONFIG_HOME/dots`, `{home}/.config/dots`
+pub fn path() -> Result<PathBuf> {
+    if let Some(path) = CONFIG_PATH.get().and_then(Option::as_ref) {
+        return Ok(path.clone());
+    }
+
+    match env::var("DOTS_CONFIG") {
+        Ok(path) if !path.is_empty() => Ok(path.into()),
+        _ => Ok(xdg_dir("XDG_CONFIG_HOME", ".config")?.join("dots")),
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    /// The default subdir of files/
-    pub default_subdir: String,
     /// The path to the files/ directory
     pub files_path: String,
+    /// The default subdir of files/
+    pub default_subdir: String,
     /// The paths that should be searched by `list()`
     pub list_paths: Vec<String>,
     /// The paths that shouldn't be searched by `list()`
     pub ignore_paths: Vec<PathBuf>,
     /// Whether to run 'list' with root privileges
     pub root: bool,
+    /// Whether to create symlinks relative to the directory they are in, instead of absolute ones
+    pub relative_symlinks: bool,
+    /// The active profile, defaults to the one named after the hostname
+    pub profile: Option<String>,
+    /// Named lists of sub-dirs of files/, from the lowest to the highest priority
+    pub profiles: BTreeMap<String, Vec<String>>,
+    /// Custom placeholders
+    pub vars: BTreeMap<String, String>,
+    /// The file containing the key for encrypted files. If it isn't set, a passphrase is asked for
+    pub key_file: Option<String>,
+    /// Commands that are run before or after add, remove and import
+    pub hooks: Vec<Hook>,
+    /// The program used to get root privileges
+    pub escalation: Backend,
+    /// How root privileges are used
+    pub escalation_mode: Mode,
+    /// The file the config was loaded from
+    #[serde(skip)]
+    pub path: PathBuf,
 }
 impl Config {
-    pub fn setup() -> Result<()> {
-        let stdin = stdin();
+    /// The sub-dirs of files/ that are searched when the sub-dir is elided, from the lowest to the highest priority.
+    /// These are the layers of the active profile, or just the default subdir if there is none.
+    pub fn layers(&self) -> Result<Vec<String>> {
+        if self.profiles.is_empty() {
+            return Ok(vec![self.default_subdir.clone()]);
+        }
 
-        let mut file_string = String::new();
+        let hostname = get_hostname()?;
 
-        let mut prompt_field = |field: &str, prompt: &str| -> Result<()> {
-            file_string.push_str(field);
-            file_string.push_str(" = ");
-            println!("{prompt}:");
-            stdin.read_line(&mut file_string)?;
-            Ok(())
+        let layers = match self.profile {
+            Some(ref profile) => Some(
+                self.profiles
+                    .get(profile)
+                    .with_context(|| format!("The profile {profile} doesn't exist"))?,
+            ),
+            None => self.profiles.get(&hostname),
         };
 
-        prompt_field(
-            "files_path",
-            "Please enter the path of the files that should be managed by dots",
-        )?;
+        layers.map_or_else(
+            || Ok(vec![self.default_subdir.clone()]),
+            |layers| {
+                layers
+                    .iter()
+                    .map(|layer| expand_with(layer, &self.vars))
+                    .collect()
+            },
+        )
+    }
+    /// Interactively creates the config file
+    pub fn setup(prompt: &dyn Prompt) -> Result<()> {
+        let input = |question: &str| prompt.input(question);
+        let split = |list: &str| -> Vec<String> {
+            list.split(',')
+                .map(|item| item.trim().to_owned())
+                .filter(|item| !item.is_empty())
+                .collect()
+        };
 
-        prompt_field("default_subdir", "Please enter the default subdir")?;
+        let config = Self {
+            files_path: input("Please enter the path of the files that should be managed by dots")?,
+            default_subdir: input("Please enter the default subdir")?,
+            list_paths: split(&input(
+                "Please enter the paths that should be searched by `dots list`, separated by commas",
+            )?),
+            ignore_paths: split(&input(
+                "Please enter the paths that should be ignored by `dots list`, separated by commas",
+            )?)
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+            root: prompt.bool_question("Should `dots list` be run with root privileges?", false)?,
+            ..Self::default()
+        };
 
-        prompt_field(
-            "list_paths",
-            "Please enter the paths that should be searched by `dots list`, separated by commas",
-        )?;
+        let path = path()?;
 
-        prompt_field(
-            "ignore_paths",
-            "Please enter the paths that should be ignored by `dots list`, separated by commas",
+        if let Some(parent) = path.parent() {
+            ops::create_dir_all(parent)?;
+        }
+        ops::write(
+            &path,
+            &toml::to_string_pretty(&config).context("serializing config")?,
         )?;
 
-        if bool_question("Should `dots list` be run with root privileges?")? {
-            file_string.push_str("root");
-        }
+        Ok(())
+    }
+    /// Loads the config from its location, see `path()`
+    pub fn load() -> Result<Self> {
+        Self::load_from(&path()?)
+    }
+    /// Loads the config at the given path, migrating it to TOML if it is in the old format
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let string = fs::read_to_string(path)
+            .with_context(|| format!("\nFailed to read config at {}. Run `dots config` to create it interactively or do so manually.", path.display()))?;
 
-        let path = format!("{}/.config/dots", home()?);
+        let mut config = match toml::from_str::<Self>(&string) {
+            Ok(config) => config,
+            Err(toml_error) => {
+                // Configs from before the switch to TOML are migrated automatically.
+                // Anything else (like a TOML config with a typo) is reported instead of being migrated.
+                let config = if looks_legacy(&string) {
+                    Self::parse_legacy(&string).ok()
+                } else {
+                    None
+                };
+                let Some(config) = config else {
+                    bail!(
+                        "Failed to parse config at {}:\n{toml_error}",
+                        path.display()
+                    );
+                };
 
-        fs::write(path, file_string)?;
+                config.migrate(path)?;
 
-        Ok(())
-    }
-    fn load() -> Result<Self> {
-        let path = format!("{}/.config/dots", home()?);
+                config
+            }
+        };
+
+        path.clone_into(&mut config.path);
 
-        let string = fs::read_to_string(&path)
-            .with_context(|| format!("\nFailed to read config at {path}. Run `dots config` to create it interactively or do so manually."))?;
+        // Replace placeholders in paths
+        config.files_path = expand_with(&config.files_path, &config.vars)?;
+        for list_path in &mut config.list_paths {
+            *list_path = expand_with(list_path, &config.vars)?;
+        }
+        for ignore_path in &mut config.ignore_paths {
+            *ignore_path = expand_with(
+                ignore_path
+                    .to_str()
+                    .context("Failed to convert path to string")?,
+                &config.vars,
+            )?
+            .into();
+        }
+        if let Some(ref mut key_file) = config.key_file {
+            *key_file = expand_with(key_file, &config.vars)?;
+        }
+        for hook in &mut config.hooks {
+            if let Some(ref mut path) = hook.path {
+                *path = expand_with(path, &config.vars)?;
+            }
+        }
+
+        ensure!(
+            !config.default_subdir.is_empty(),
+            "default_subdir is empty or not in the config. Maybe try adding something like `default_subdir = \"common\"` to your dots config file"
+        );
 
+        Ok(config)
+    }
+    /// Parses the old key = value config format
+    fn parse_legacy(string: &str) -> Result<Self> {
         let mut config = Self::default();
 
         for line in string.lines() {
@@ -85,16 +218,113 @@ impl Config {
                 },
                 None => match line.trim() {
                     "root" => config.root = true,
+                    "" => {}
                     other => bail!("Unknown config key: {other}"),
                 },
             }
         }
 
-        ensure!(
-            !config.default_subdir.is_empty(),
-            "default_subdir is empty or not in the config. Maybe try adding something like `default_subdir = common` to your dots config file"
+        Ok(config)
+    }
+    /// Rewrites the config at the path as TOML, keeping the old one at {path}.legacy
+    fn migrate(&self, path: &Path) -> Result<()> {
+        if dry_run() {
+            return Ok(());
+        }
+
+        let mut legacy_path = path.as_os_str().to_owned();
+        legacy_path.push(".legacy");
+        let legacy_path = PathBuf::from(legacy_path);
+
+        fs::copy(path, &legacy_path)
+            .with_context(|| format!("backing up legacy config to {}", legacy_path.display()))?;
+        // Write to the existing file, to keep its owner
+        fs::write(
+            path,
+            toml::to_string_pretty(self).context("serializing config")?,
+        )
+        .with_context(|| format!("writing migrated config to {}", path.display()))?;
+
+        eprintln!(
+            "Migrated config at {} to TOML, the old one was kept at {}",
+            path.display(),
+            legacy_path.display()
         );
 
-        Ok(config)
+        Ok(())
+    }
+}
+
+/// Whether the config is in the old `key = value` format.
+/// Values in the old format are never quoted, arrays or tables, and `root` is the only bare key.
+fn looks_legacy(string: &str) -> bool {
+    string
+        .lines()
+        .map(str::trim)
+        .all(|line| match line.split_once('=') {
+            Some((key, value)) => {
+                matches!(
+                    key.trim(),
+                    "default_subdir" | "files_path" | "list_paths" | "ignore_paths" | "root"
+                ) && !value.contains(['"', '\'', '[', '{'])
+            }
+            None => matches!(line, "root" | ""),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use anyhow::Result;
+
+    use super::{Config, looks_legacy};
+
+    const LEGACY: &str = "\
+default_subdir = common
+files_path = /home/user/.config/atem/files
+list_paths = /home/user, /etc
+ignore_paths = /home/user/.cache
+root
+";
+
+    #[test]
+    fn parses_legacy_configs() -> Result<()> {
+        assert!(looks_legacy(LEGACY));
+
+        let config = Config::parse_legacy(LEGACY)?;
+        assert_eq!(config.default_subdir, "common");
+        assert_eq!(config.files_path, "/home/user/.config/atem/files");
+        assert_eq!(config.list_paths, ["/home/user", "/etc"]);
+        assert_eq!(config.ignore_paths, [PathBuf::from("/home/user/.cache")]);
+        assert!(config.root);
+        Ok(())
+    }
+
+    #[test]
+    fn toml_with_typos_doesnt_look_legacy() {
+        for toml in [
+            "files_path = \"/home/user/files\nlist_paths = []",
+            "defualt_subdir = \"common\"",
+            "list_paths = [\"/home/user\",",
+            "default_subdir = \"common\"\n[profiles\n",
+        ] {
+            assert!(!looks_legacy(toml), "{toml}");
+        }
+    }
+
+    #[test]
+    fn toml_with_typos_isnt_migrated() -> Result<()> {
+        let path = env::temp_dir().join(format!("dots-config-test-{}", process::id()));
+        let contents = "default_subdir = \"common\nfiles_path = \"/home/user/files\"\n";
+        fs::write(&path, contents)?;
+
+        let result = Config::load_from(&path);
+        let after = fs::read_to_string(&path)?;
+        fs::remove_file(&path)?;
+
+        assert!(result.is_err());
+        assert_eq!(after, contents);
+        Ok(())
     }
 }
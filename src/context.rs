@@ -0,0 +1,57 @@
+use std::{
+    io::{self, ErrorKind},
+    sync::OnceLock,
+};
+
+use anyhow::Result;
+
+use crate::{config::Config, escalate::Escalate, prompt::Prompt, secret::read_secret};
+
+/// Everything operations need besides their arguments: the config, and how to interact with the user
+pub struct Context {
+    pub config: Config,
+    pub prompt: Box<dyn Prompt>,
+    pub escalate: Box<dyn Escalate>,
+    /// The key or passphrase for encrypted files, read once it is first needed
+    secret: OnceLock<Vec<u8>>,
+}
+impl Context {
+    #[must_use]
+    pub fn new(config: Config, prompt: Box<dyn Prompt>, escalate: Box<dyn Escalate>) -> Self {
+        Self {
+            config,
+            prompt,
+            escalate,
+            secret: OnceLock::new(),
+        }
+    }
+    /// The key or passphrase for encrypted files (which is only asked for once)
+    pub fn secret(&self) -> Result<&[u8]> {
+        if let Some(secret) = self.secret.get() {
+            return Ok(secret);
+        }
+
+        let secret = read_secret(&self.config, &*self.prompt)?;
+
+        Ok(self.secret.get_or_init(|| secret))
+    }
+    /// Runs the action, escalating and retrying it once if it fails because it requires root privileges
+    pub fn escalating<T>(&self, action: impl Fn() -> Result<T>) -> Result<T> {
+        match action() {
+            Err(error) if requires_root(&error) => {
+                self.escalate.escalate(&format!("{error}"))?; // just formatting the error should be fine for handling context
+                action()
+            }
+            other => other,
+        }
+    }
+}
+
+/// Whether the error was caused by missing permissions
+#[must_use]
+pub fn requires_root(error: &anyhow::Error) -> bool {
+    error
+        .root_cause()
+        .downcast_ref::<io::Error>()
+        .is_some_and(|io_error| io_error.kind() == ErrorKind::PermissionDenied)
+}
@@ -0,0 +1,127 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{self, File, FileTimes, Metadata},
+    os::unix::fs::{MetadataExt as _, lchown, symlink},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+
+/// Something that couldn't be copied faithfully
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: String,
+}
+impl Display for Skipped {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Skipped {}: {}", self.path.display(), self.reason)
+    }
+}
+
+/// Recursively copies the source path to the target path.
+///
+/// Symlinks are recreated instead of followed, and mode bits and modification times are kept
+/// (except for the times of symlinks, which std cant set).
+/// If `preserve_owner` is set, the owner and group are kept as well, which usually requires root.
+/// Special files (fifos, sockets, devices) are skipped.
+///
+/// Returns everything that had to be skipped.
+pub fn copy_tree(source: &Path, target: &Path, preserve_owner: bool) -> Result<Vec<Skipped>> {
+    let mut skipped = Vec::new();
+    copy_entry(source, target, preserve_owner, &mut skipped)?;
+    Ok(skipped)
+}
+
+/// Prints everything that had to be skipped
+pub fn report(skipped: &[Skipped]) {
+    for skipped in skipped {
+        eprintln!("{skipped}");
+    }
+}
+
+#[expect(clippy::filetype_is_file)] // Special files are explicitly handled
+fn copy_entry(
+    source: &Path,
+    target: &Path,
+    preserve_owner: bool,
+    skipped: &mut Vec<Skipped>,
+) -> Result<()> {
+    let metadata = fs::symlink_metadata(source)
+        .with_context(|| format!("Failed to get metadata for {}", source.display()))?;
+
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        let destination = fs::read_link(source).with_context(|| {
+            format!("reading symlink destination for path {}", source.display())
+        })?;
+
+        symlink(destination, target)
+            .with_context(|| format!("creating symlink {}", target.display()))?;
+    } else if file_type.is_dir() {
+        fs::create_dir_all(target)
+            .with_context(|| format!("creating directory {}", target.display()))?;
+
+        for entry in fs::read_dir(source)
+            .with_context(|| format!("reading directory {}", source.display()))?
+        {
+            let entry = entry?;
+
+            copy_entry(
+                &entry.path(),
+                &target.join(entry.file_name()),
+                preserve_owner,
+                skipped,
+            )?;
+        }
+
+        // Set the times after populating the directory, as that changes them
+        set_times(target, &metadata, skipped);
+    } else if file_type.is_file() {
+        fs::copy(source, target)
+            .with_context(|| format!("copying {} to {}", source.display(), target.display()))?;
+
+        set_times(target, &metadata, skipped);
+    } else {
+        skipped.push(Skipped {
+            path: source.into(),
+            reason: "special files are not supported".into(),
+        });
+        return Ok(());
+    }
+
+    // Change the owner before the permissions, as it may clear setuid/setgid bits
+    if preserve_owner && let Err(e) = lchown(target, Some(metadata.uid()), Some(metadata.gid())) {
+        skipped.push(Skipped {
+            path: source.into(),
+            reason: format!("failed to preserve owner: {e}"),
+        });
+    }
+
+    // Set the permissions last, in case they dont allow writing.
+    // fs::copy already sets them for files, but changing the owner may have cleared setuid/setgid since
+    if !file_type.is_symlink() {
+        fs::set_permissions(target, metadata.permissions())
+            .with_context(|| format!("setting permissions of {}", target.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Sets the access and modification times of the target to the ones in the metadata
+fn set_times(target: &Path, metadata: &Metadata, skipped: &mut Vec<Skipped>) {
+    let result = metadata.accessed().and_then(|accessed| {
+        let times = FileTimes::new()
+            .set_accessed(accessed)
+            .set_modified(metadata.modified()?);
+
+        File::open(target)?.set_times(times)
+    });
+
+    if let Err(e) = result {
+        skipped.push(Skipped {
+            path: target.into(),
+            reason: format!("failed to preserve modification time: {e}"),
+        });
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result, anyhow};
+
+use crate::{
+    SILENT,
+    context::Context,
+    manifest, source,
+    status::{State, state},
+    util::{cli_path, get_hostname, system_path},
+};
+
+/// A path in files/ and its state on the system
+pub struct Covered {
+    /// The path in the format taken by `add`
+    pub path: String,
+    pub state: State,
+}
+
+/// The result of `check_coverage`
+pub struct Coverage {
+    pub paths: Vec<Covered>,
+    /// The sub-dirs of files/ that don't apply to this host
+    pub not_applicable: Vec<String>,
+}
+
+/// Prints how much of files/ is deployed on the system.
+/// With `missing_only`, only the paths that aren't deployed are printed, in the format taken by `add`.
+pub fn print_coverage(context: &Context, missing_only: bool) -> Result<()> {
+    let Coverage {
+        paths,
+        not_applicable,
+    } = check_coverage(context)?;
+
+    for covered in &paths {
+        if missing_only {
+            if !covered.state.in_sync() {
+                println!("{}", covered.path);
+            }
+        } else {
+            println!("{:<10} {}", covered.state, covered.path);
+        }
+    }
+
+    if missing_only || SILENT.get().is_some_and(|silent| *silent) {
+        return Ok(());
+    }
+
+    if !not_applicable.is_empty() {
+        println!(
+            "Sub-dirs that don't apply to this host: {}",
+            not_applicable.join(", ")
+        );
+    }
+
+    let deployed = paths
+        .iter()
+        .filter(|covered| covered.state.in_sync())
+        .count();
+    let total = paths.len();
+
+    // Exact, as long as files/ contains less than 2^53 paths
+    #[expect(clippy::cast_precision_loss)]
+    let percentage = if total == 0 {
+        100.0
+    } else {
+        deployed as f64 / total as f64 * 100.0
+    };
+
+    println!("{deployed} of {total} paths deployed ({percentage:.1}%)");
+
+    Ok(())
+}
+
+/// Determines the state of every path in the sub-dirs of files/ that apply to this host.
+///
+/// Directories that exist on the system (and aren't deployed as a whole) are recursed into.
+/// Paths that are shadowed by a higher priority layer are skipped.
+pub fn check_coverage(context: &Context) -> Result<Coverage> {
+    let config = &context.config;
+
+    let layers = config.layers()?;
+    let mut applicable: BTreeSet<String> = layers.iter().cloned().collect();
+    applicable.insert(config.default_subdir.clone());
+    applicable.insert(get_hostname()?);
+
+    let mut coverage = Coverage {
+        paths: Vec::new(),
+        not_applicable: Vec::new(),
+    };
+
+    // The system paths of directories added with --copy can only be known from the manifest
+    let copies = if manifest::path(config).exists() {
+        manifest::load(config)?
+            .into_iter()
+            .filter(|entry| entry.copy)
+            .map(|entry| system_path(config, &entry.path))
+            .collect::<Result<_>>()?
+    } else {
+        BTreeSet::new()
+    };
+
+    for entry in fs::read_dir(&config.files_path)
+        .with_context(|| format!("reading files directory {}", config.files_path))?
+    {
+        let entry = entry?;
+        let subdir = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| anyhow!("Failed to convert sub-dir to string"))?;
+
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        if applicable.contains(&subdir) {
+            walk(
+                context,
+                &layers,
+                &copies,
+                &entry.path(),
+                Path::new(&subdir),
+                &mut coverage.paths,
+            )?;
+        } else {
+            coverage.not_applicable.push(subdir);
+        }
+    }
+
+    coverage.paths.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    coverage.not_applicable.sort_unstable();
+
+    Ok(coverage)
+}
+
+/// Determines the state of the entries of the directory in files/, see `check_coverage()`
+fn walk(
+    context: &Context,
+    layers: &[String],
+    copies: &BTreeSet<PathBuf>,
+    dir: &Path,
+    relative_dir: &Path,
+    paths: &mut Vec<Covered>,
+) -> Result<()> {
+    let config = &context.config;
+
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("reading directory {}", dir.display()))?
+    {
+        let entry = entry?;
+        let file_name = entry.file_name();
+
+        // Templates and secrets are deployed without their suffix
+        let file_name = source::without_suffix(Path::new(&file_name));
+
+        let config_path: PathBuf = dir.join(&file_name);
+        let relative_path = relative_dir.join(&file_name);
+        let system_path = system_path(config, &relative_path)?;
+
+        let is_dir = entry.file_type()?.is_dir();
+
+        let symlink_state = state(context, &config_path, &system_path, false)?;
+        // The path may have been added with --copy.
+        // Directories only count as copied if they are in the manifest as such, as parents of deployed paths exist on the system anyways.
+        let state = if matches!(symlink_state, State::Replaced)
+            && (!is_dir || copies.contains(&system_path))
+        {
+            state(context, &config_path, &system_path, true)?
+        } else {
+            symlink_state
+        };
+
+        if !state.in_sync()
+            && is_dir
+            && fs::symlink_metadata(&system_path).is_ok_and(|metadata| metadata.is_dir())
+        {
+            walk(context, layers, copies, &config_path, &relative_path, paths)?;
+            continue;
+        }
+
+        let path = cli_path(config, &relative_path)?;
+
+        // Skip paths where the elided path resolves to a higher priority layer
+        if !path.starts_with('/')
+            && relative_dir
+                .components()
+                .next()
+                .and_then(|subdir| subdir.as_os_str().to_str())
+                .is_some_and(|subdir| layers.iter().any(|layer| layer == subdir))
+        {
+            continue;
+        }
+
+        paths.push(Covered { path, state });
+    }
+
+    Ok(())
+}
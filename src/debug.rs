@@ -1,19 +1,36 @@
-use anyhow::Result;
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
 
 use crate::{
-    DebugCommands::{self, ConfigPath, SystemPath},
-    util::{config_path, system_path},
+    SILENT,
+    context::Context,
+    output::{Entry, Format, Kind, print_entries},
 };
 
-pub fn debug(debug_command: DebugCommands) -> Result<()> {
-    match debug_command {
-        ConfigPath { path } => {
-            println!("{}", config_path(&path)?.display());
-        }
-        SystemPath { path } => {
-            println!("{}", system_path(&path)?.display());
-        }
-    }
+/// Which path `debug` prints
+#[derive(Clone, Copy)]
+pub enum Debug {
+    ConfigPath,
+    SystemPath,
+}
+
+pub fn debug(context: &Context, debug: Debug, path: &Path, format: Format) -> Result<()> {
+    // Only the paths are printed, so the state isn't needed
+    let entry = Entry::paths(
+        context,
+        path.to_str().context("Failed to convert path to string")?,
+        Kind::Symlink,
+    )?;
 
-    Ok(())
+    print_entries(&[entry], format, |entry| match debug {
+        Debug::ConfigPath => {
+            if SILENT.get().is_some_and(|silent| *silent) {
+                println!("{}", entry.config_path.display());
+            } else {
+                println!("{} (layer {})", entry.config_path.display(), entry.subdir);
+            }
+        }
+        Debug::SystemPath => println!("{}", entry.system_path.display()),
+    })
 }
@@ -0,0 +1,404 @@
+use std::{
+    cmp::{max, min},
+    collections::BTreeSet,
+    ffi::OsString,
+    fs,
+    path::Path,
+};
+
+use anyhow::{Context as _, Result};
+use sha2::{Digest as _, Sha256};
+
+use crate::{source::Source, util::paths_equal};
+
+/// The amount of unchanged lines shown around each change
+const CONTEXT: usize = 3;
+
+/// The maximum size of the table used for computing line diffs, bigger files only get a summary
+const MAX_TABLE_SIZE: usize = 1 << 24;
+
+/// A line of a diff
+enum Line<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Prints the changes that overwriting the system path with the source would make.
+/// The contents of secrets are never shown.
+pub fn print_diff(source: &Source, system_path: &Path) -> Result<()> {
+    let config_path = &source.path;
+
+    let system_metadata = fs::symlink_metadata(system_path).with_context(|| {
+        format!(
+            "Failed to get metadata for system path {}",
+            system_path.display()
+        )
+    })?;
+    let config_metadata = fs::symlink_metadata(config_path).with_context(|| {
+        format!(
+            "Failed to get metadata for config path {}",
+            config_path.display()
+        )
+    })?;
+
+    if system_metadata.is_dir() && config_metadata.is_dir() {
+        println!(
+            "--- {} (system)\n+++ {} (config)",
+            system_path.display(),
+            source.origin.display()
+        );
+        print_dir_diff(config_path, system_path, Path::new(""))
+    } else if system_metadata.is_file() && config_metadata.is_file() {
+        let system_bytes = fs::read(system_path).context("reading system file")?;
+        let config_bytes = fs::read(config_path).context("reading config file")?;
+
+        println!(
+            "--- {} (system)\n+++ {} (config)",
+            system_path.display(),
+            source.origin.display()
+        );
+
+        if source.secret {
+            println!("Contents differ (not shown, as the config path is a secret)");
+            println!("system: {} bytes", system_bytes.len());
+            println!("config: {} bytes", config_bytes.len());
+        } else if let (Some(system_text), Some(config_text)) =
+            (text(&system_bytes), text(&config_bytes))
+        {
+            print_text_diff(system_text, config_text);
+        } else {
+            println!("Binary files differ");
+            print_binary_summary("system", &system_bytes);
+            print_binary_summary("config", &config_bytes);
+        }
+
+        Ok(())
+    } else {
+        println!("system: {}", describe(system_path)?);
+        println!("config: {}", describe(config_path)?);
+
+        Ok(())
+    }
+}
+
+/// Returns the bytes as a string, if they are plausibly text
+fn text(bytes: &[u8]) -> Option<&str> {
+    if bytes.contains(&0) {
+        None
+    } else {
+        str::from_utf8(bytes).ok()
+    }
+}
+
+/// Prints the size and SHA-256 hash of a binary file
+fn print_binary_summary(name: &str, bytes: &[u8]) {
+    let hash = Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .concat();
+
+    println!("{name}: {} bytes, sha256 {hash}", bytes.len());
+}
+
+/// Describes the type of the path (and the destination for symlinks)
+fn describe(path: &Path) -> Result<String> {
+    let metadata = fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to get metadata for {}", path.display()))?;
+
+    Ok(if metadata.is_symlink() {
+        let destination = fs::read_link(path)
+            .with_context(|| format!("reading symlink destination for path {}", path.display()))?;
+        format!("symlink to {}", destination.display())
+    } else if metadata.is_dir() {
+        "directory".into()
+    } else if metadata.is_file() {
+        format!("file of {} bytes", metadata.len())
+    } else {
+        "special file".into()
+    })
+}
+
+/// Recursively prints which entries only exist in one of the directories or differ between them
+fn print_dir_diff(config_dir: &Path, system_dir: &Path, relative: &Path) -> Result<()> {
+    let entries = |dir: &Path| -> Result<BTreeSet<OsString>> {
+        fs::read_dir(dir)
+            .with_context(|| format!("reading directory {}", dir.display()))?
+            .map(|entry| Ok(entry?.file_name()))
+            .collect()
+    };
+
+    let system_entries = entries(system_dir)?;
+    let config_entries = entries(config_dir)?;
+
+    for name in system_entries.union(&config_entries) {
+        let relative = relative.join(name);
+        let system_path = system_dir.join(name);
+        let config_path = config_dir.join(name);
+
+        if !config_entries.contains(name) {
+            println!("- {}", relative.display());
+        } else if !system_entries.contains(name) {
+            println!("+ {}", relative.display());
+        } else if fs::symlink_metadata(&system_path).is_ok_and(|metadata| metadata.is_dir())
+            && fs::symlink_metadata(&config_path).is_ok_and(|metadata| metadata.is_dir())
+        {
+            print_dir_diff(&config_path, &system_path, &relative)?;
+        } else if paths_equal(&config_path, &system_path).is_err() {
+            println!("~ {}", relative.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a unified diff of the two texts
+fn print_text_diff(old: &str, new: &str) {
+    // Keep the line endings, so differences in them (like a missing newline at the end) are shown
+    let old: Vec<_> = old.split_inclusive('\n').collect();
+    let new: Vec<_> = new.split_inclusive('\n').collect();
+
+    let Some(lines) = diff_lines(&old, &new) else {
+        println!(
+            "Files are too large to diff ({} and {} lines)",
+            old.len(),
+            new.len()
+        );
+        return;
+    };
+
+    for line in hunks(&lines) {
+        println!("{line}");
+    }
+}
+
+/// Computes the line diff of old and new using their longest common subsequence.
+/// Returns None if the files are too large.
+#[expect(clippy::arithmetic_side_effects)] // All indices are bounded by the lengths of the inputs
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Option<Vec<Line<'a>>> {
+    // Strip the common prefix and suffix, as they dont need to be part of the table
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|&(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(old, new)| old == new)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let (height, width) = (old_middle.len() + 1, new_middle.len() + 1);
+    if height.checked_mul(width)? > MAX_TABLE_SIZE {
+        return None;
+    }
+
+    // lcs[i * width + j] is the length of the longest common subsequence of old_middle[i..] and new_middle[j..]
+    let mut lcs = vec![0_u32; height * width];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lcs[i * width + j] = if old_middle[i] == new_middle[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                max(lcs[(i + 1) * width + j], lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<_> = old[..prefix].iter().map(|line| Line::Equal(line)).collect();
+
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() && j < new_middle.len() {
+        if old_middle[i] == new_middle[j] {
+            lines.push(Line::Equal(old_middle[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+            lines.push(Line::Delete(old_middle[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Insert(new_middle[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old_middle[i..].iter().map(|line| Line::Delete(line)));
+    lines.extend(new_middle[j..].iter().map(|line| Line::Insert(line)));
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Equal(line)),
+    );
+
+    Some(lines)
+}
+
+/// The lines to print for the changed lines with some context, grouped into hunks
+#[expect(clippy::arithmetic_side_effects)] // All indices are bounded by the amount of lines
+fn hunks(lines: &[Line]) -> Vec<String> {
+    let mut output = Vec::new();
+
+    let changes: Vec<_> = lines
+        .iter()
+        .enumerate()
+        .filter(|&(_, line)| !matches!(*line, Line::Equal(_)))
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut change = 0;
+    while change < changes.len() {
+        let start = changes[change].saturating_sub(CONTEXT);
+
+        // Merge following changes, if their context would overlap
+        while change + 1 < changes.len() && changes[change + 1] <= changes[change] + 2 * CONTEXT {
+            change += 1;
+        }
+        let end = min(changes[change] + 1 + CONTEXT, lines.len());
+        change += 1;
+
+        let counts = |lines: &[Line]| {
+            lines.iter().fold((0, 0), |(old, new), line| match *line {
+                Line::Equal(_) => (old + 1, new + 1),
+                Line::Delete(_) => (old + 1, new),
+                Line::Insert(_) => (old, new + 1),
+            })
+        };
+        let (old_before, new_before) = counts(&lines[..start]);
+        let (old_len, new_len) = counts(&lines[start..end]);
+
+        // Empty ranges start at the line before them
+        let range_start = |before: usize, len: usize| if len == 0 { before } else { before + 1 };
+
+        output.push(format!(
+            "@@ -{},{old_len} +{},{new_len} @@",
+            range_start(old_before, old_len),
+            range_start(new_before, new_len)
+        ));
+
+        for line in &lines[start..end] {
+            let (prefix, line) = match *line {
+                Line::Equal(line) => (' ', line),
+                Line::Delete(line) => ('-', line),
+                Line::Insert(line) => ('+', line),
+            };
+
+            if let Some(line) = line.strip_suffix('\n') {
+                // Show carriage returns, as they'd be invisible otherwise
+                output.push(line.strip_suffix('\r').map_or_else(
+                    || format!("{prefix}{line}"),
+                    |line| format!("{prefix}{line}^M"),
+                ));
+            } else {
+                output.push(format!("{prefix}{line}"));
+                output.push("\\ No newline at end of file".to_owned());
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_lines, hunks};
+
+    /// The hunks of the diff between the texts, split into lines
+    fn diff(old: &str, new: &str) -> Vec<String> {
+        let old: Vec<_> = old.split_inclusive('\n').collect();
+        let new: Vec<_> = new.split_inclusive('\n').collect();
+
+        diff_lines(&old, &new).map_or_else(Vec::new, |lines| hunks(&lines))
+    }
+
+    #[test]
+    fn equal_texts_have_no_hunks() {
+        assert!(diff("a\nb\nc\n", "a\nb\nc\n").is_empty());
+    }
+
+    #[test]
+    fn changed_line_has_context() {
+        assert_eq!(
+            diff(
+                "1\n2\n3\n4\n5\n6\n7\n8\n9\n",
+                "1\n2\n3\n4\nfive\n6\n7\n8\n9\n"
+            ),
+            [
+                "@@ -2,7 +2,7 @@",
+                " 2",
+                " 3",
+                " 4",
+                "-5",
+                "+five",
+                " 6",
+                " 7",
+                " 8"
+            ]
+        );
+    }
+
+    #[test]
+    fn distant_changes_get_separate_hunks() {
+        let old: Vec<_> = (1..=20).map(|line| format!("{line}\n")).collect();
+        let mut new = old.clone();
+        new[1] = "two\n".to_owned();
+        new.remove(18);
+
+        assert_eq!(
+            diff(&old.concat(), &new.concat()),
+            [
+                "@@ -1,5 +1,5 @@",
+                " 1",
+                "-2",
+                "+two",
+                " 3",
+                " 4",
+                " 5",
+                "@@ -16,5 +16,4 @@",
+                " 16",
+                " 17",
+                " 18",
+                "-19",
+                " 20"
+            ]
+        );
+    }
+
+    #[test]
+    fn close_changes_are_merged() {
+        assert_eq!(
+            diff("a\nb\nc\nd\ne\n", "A\nb\nc\nd\nE\n"),
+            ["@@ -1,5 +1,5 @@", "-a", "+A", " b", " c", " d", "-e", "+E"]
+        );
+    }
+
+    #[test]
+    fn missing_newline_at_the_end() {
+        assert_eq!(
+            diff("a\n", "a"),
+            [
+                "@@ -1,1 +1,1 @@",
+                "-a",
+                "+a",
+                "\\ No newline at end of file"
+            ]
+        );
+    }
+
+    #[test]
+    fn carriage_returns_are_shown() {
+        assert_eq!(
+            diff("a\nb\n", "a\r\nb\n"),
+            ["@@ -1,2 +1,2 @@", "-a", "+a^M", " b"]
+        );
+    }
+
+    #[test]
+    fn empty_ranges_start_at_the_line_before() {
+        assert_eq!(diff("", "a\nb\n"), ["@@ -0,0 +1,2 @@", "+a", "+b"]);
+        assert_eq!(diff("a\nb\n", ""), ["@@ -1,2 +0,0 @@", "-a", "-b"]);
+    }
+}
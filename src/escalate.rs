@@ -0,0 +1,300 @@
+//! Getting root privileges
+
+use std::{
+    env::{self, current_exe},
+    ffi::{OsStr, OsString},
+    io::{self, Read as _, Write as _},
+    os::unix::fs::PermissionsExt as _,
+    path::PathBuf,
+    process::{Command, Stdio, exit},
+    sync::OnceLock,
+};
+
+use anyhow::{Context as _, Result, bail, ensure};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    SILENT, batch, config, ops,
+    util::{home, xdg_dir},
+};
+
+/// How dots gets root privileges when an operation requires them, supplied by the caller
+pub trait Escalate {
+    /// Called when `failed_action` failed because it requires root privileges.
+    ///
+    /// Implementations either get root privileges and return Ok, after which the action is retried,
+    /// rerun the whole program with root privileges (and don't return), or return an error.
+    fn escalate(&self, failed_action: &str) -> Result<()>;
+}
+
+/// Reruns the current command with root privileges, using the backend
+pub struct Rerun(pub Backend);
+impl Escalate for Rerun {
+    fn escalate(&self, failed_action: &str) -> Result<()> {
+        if self.0 == Backend::None {
+            return Never.escalate(failed_action);
+        }
+
+        rerun_with_root(self.0, failed_action);
+    }
+}
+
+/// Never escalates, returning an error instead
+pub struct Never;
+impl Escalate for Never {
+    fn escalate(&self, failed_action: &str) -> Result<()> {
+        bail!("{failed_action} requires root privileges")
+    }
+}
+
+/// The program used to get root privileges
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Sudo,
+    Doas,
+    Run0,
+    Pkexec,
+    /// Fail instead of getting root privileges
+    None,
+}
+impl Backend {
+    /// Builds a command that runs dots with root privileges, keeping the environment dots depends on
+    fn command(self) -> Result<Command> {
+        let program = match self {
+            Self::Sudo => "sudo",
+            Self::Doas => "doas",
+            Self::Run0 => "run0",
+            Self::Pkexec => "pkexec",
+            Self::None => bail!("Getting root privileges is disabled in the config"),
+        };
+
+        let mut command = Command::new(find_in_path(program)?);
+
+        // Not all backends can set env variables themselves
+        command.arg(find_in_path("env")?);
+
+        let user = env::var("USER").unwrap_or_default();
+        command
+            // Preserve $HOME
+            .arg(format!("HOME={}", home()?))
+            // Preserve $USER, for the {user} placeholder
+            .arg(format!("USER={user}"))
+            // Use the same config and backup store
+            .arg(format!("DOTS_CONFIG={}", config::path()?.display()))
+            .arg(format!(
+                "XDG_STATE_HOME={}",
+                xdg_dir("XDG_STATE_HOME", ".local/state")?.display()
+            ))
+            .arg(current_exe().context("Failed to get the path of dots")?);
+
+        Ok(command)
+    }
+}
+
+/// How root privileges are used
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Rerun the whole command with root privileges
+    #[default]
+    Rerun,
+    /// Only run the filesystem operations that require root privileges in a helper process
+    Helper,
+}
+
+/// The backend used to run the helper, if the helper mode is enabled
+pub static HELPER: OnceLock<Backend> = OnceLock::new();
+
+/// A filesystem operation that can be run in the helper, see `ops`
+#[derive(Subcommand, Debug)]
+pub enum Operation {
+    Remove {
+        path: PathBuf,
+    },
+    CreateDirAll {
+        path: PathBuf,
+    },
+    Symlink {
+        target: PathBuf,
+        link: PathBuf,
+    },
+    Copy {
+        #[arg(long)]
+        preserve_owner: bool,
+        source: PathBuf,
+        target: PathBuf,
+    },
+    Rename {
+        source: PathBuf,
+        target: PathBuf,
+    },
+    Write {
+        path: PathBuf,
+        /// Passed to the helper on stdin, as arguments are visible to every user and limited in size
+        #[arg(skip)]
+        contents: String,
+    },
+}
+impl Operation {
+    /// The arguments of the `__helper` subcommand that runs the operation
+    fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["__helper".into()];
+
+        let values: Vec<&OsStr> = match *self {
+            Self::Remove { ref path } => {
+                args.push("remove".into());
+                vec![path.as_os_str()]
+            }
+            Self::CreateDirAll { ref path } => {
+                args.push("create-dir-all".into());
+                vec![path.as_os_str()]
+            }
+            Self::Symlink {
+                ref target,
+                ref link,
+            } => {
+                args.push("symlink".into());
+                vec![target.as_os_str(), link.as_os_str()]
+            }
+            Self::Copy {
+                preserve_owner,
+                ref source,
+                ref target,
+            } => {
+                args.push("copy".into());
+                if preserve_owner {
+                    args.push("--preserve-owner".into());
+                }
+                vec![source.as_os_str(), target.as_os_str()]
+            }
+            Self::Rename {
+                ref source,
+                ref target,
+            } => {
+                args.push("rename".into());
+                vec![source.as_os_str(), target.as_os_str()]
+            }
+            Self::Write { ref path, .. } => {
+                args.push("write".into());
+                vec![path.as_os_str()]
+            }
+        };
+
+        // Separate the values, so they cant be mistaken for options
+        args.push("--".into());
+        args.extend(values.into_iter().map(Into::into));
+
+        args
+    }
+    /// Runs the operation in this process (which is the helper)
+    pub fn run(&self) -> Result<()> {
+        match *self {
+            Self::Remove { ref path } => ops::remove(path),
+            Self::CreateDirAll { ref path } => ops::create_dir_all(path),
+            Self::Symlink {
+                ref target,
+                ref link,
+            } => ops::symlink(target, link)
+                .with_context(|| format!("creating symlink {}", link.display())),
+            Self::Copy {
+                preserve_owner,
+                ref source,
+                ref target,
+            } => ops::copy(source, target, preserve_owner),
+            Self::Rename {
+                ref source,
+                ref target,
+            } => ops::rename(source, target)
+                .with_context(|| format!("moving {} to {}", source.display(), target.display())),
+            Self::Write { ref path, .. } => {
+                let mut contents = String::new();
+                io::stdin()
+                    .read_to_string(&mut contents)
+                    .context("reading contents from stdin")?;
+
+                ops::write(path, &contents)
+            }
+        }
+    }
+    /// Runs the operation in a helper process with root privileges
+    pub fn run_in_helper(&self, backend: Backend) -> Result<()> {
+        let mut command = backend.command()?;
+        command.args(self.args());
+
+        let status = if let Self::Write { ref contents, .. } = *self {
+            let mut helper = command
+                .stdin(Stdio::piped())
+                .spawn()
+                .context("running privileged helper")?;
+
+            // Dropping stdin closes it, so the helper knows it got all of the contents
+            helper
+                .stdin
+                .take()
+                .context("Failed to open stdin of privileged helper")?
+                .write_all(contents.as_bytes())
+                .context("passing contents to privileged helper")?;
+
+            helper.wait().context("waiting for privileged helper")?
+        } else {
+            command.status().context("running privileged helper")?
+        };
+
+        ensure!(status.success(), "Privileged helper failed ({status})");
+
+        Ok(())
+    }
+}
+
+/// Inform the user of the `failed_action` and rerun with root privileges
+pub fn rerun_with_root(backend: Backend, failed_action: &str) -> ! {
+    if !SILENT.get().is_some_and(|silent| *silent) {
+        println!("{failed_action} requires root privileges");
+    }
+    rerun_with_root_args(backend, &batch::done_args());
+}
+
+/// Rerun with root privileges, and add the provided args to the command.
+/// If that isn't possible, the error is printed and dots exits.
+///
+/// # Panics
+/// If the command can't be run
+#[expect(clippy::expect_used)] // We dont return anyways, so we might as well panic
+pub fn rerun_with_root_args(backend: Backend, args: &[impl AsRef<OsStr>]) -> ! {
+    let mut command = match backend.command() {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("Error: {error:#}");
+            exit(1);
+        }
+    };
+
+    let status = command
+        .args(env::args().skip(1))
+        .args(args)
+        .spawn()
+        .expect("Failed to spawn child process")
+        .wait()
+        .expect("Failed to wait on child process");
+
+    if status.success() {
+        exit(0);
+    } else {
+        exit(status.code().unwrap_or(1));
+    }
+}
+
+/// Finds the executable in $PATH
+fn find_in_path(program: &str) -> Result<PathBuf> {
+    env::split_paths(&env::var_os("PATH").unwrap_or_default())
+        .map(|dir| dir.join(program))
+        .find(|path| {
+            path.metadata().is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+        .with_context(|| format!("Failed to find {program} in PATH"))
+}
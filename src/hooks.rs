@@ -0,0 +1,131 @@
+//! Commands from the config that are run before or after add, remove and import
+
+use std::{path::Path, process::Command};
+
+use anyhow::{Context as _, Result, bail};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    SILENT,
+    config::Config,
+    ops::dry_run,
+    util::{config_path, system_path},
+};
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum When {
+    Pre,
+    Post,
+}
+
+/// The commands hooks can run around
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookCommand {
+    Add,
+    Remove,
+    Import,
+}
+
+/// A `[[hooks]]` entry in the config
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    /// Whether to run before or after the command
+    pub when: When,
+    /// The commands to run around, all of them if empty
+    #[serde(default)]
+    pub commands: Vec<HookCommand>,
+    /// Only run for system paths matching this glob, for all paths if unset
+    pub path: Option<String>,
+    /// The shell command to run
+    pub run: String,
+    /// Whether to abort if the hook fails, instead of only reporting the failure
+    #[serde(default)]
+    pub abort_on_failure: bool,
+}
+impl Hook {
+    fn applies(&self, when: When, command: HookCommand, system_path: &Path) -> Result<bool> {
+        if self.when != when || !(self.commands.is_empty() || self.commands.contains(&command)) {
+            return Ok(false);
+        }
+
+        let Some(ref pattern) = self.path else {
+            return Ok(true);
+        };
+
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+
+        Ok(Pattern::new(pattern)
+            .with_context(|| format!("Invalid hook path {pattern}"))?
+            .matches_path_with(system_path, options))
+    }
+}
+
+/// Runs `action`, with the matching hooks before and after it
+pub fn with_hooks<T>(
+    config: &Config,
+    command: HookCommand,
+    cli_path: &Path,
+    action: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    if config.hooks.is_empty() {
+        return action();
+    }
+
+    let config_path = config_path(config, cli_path)?;
+    let system_path = system_path(config, cli_path)?;
+
+    run(config, When::Pre, command, &config_path, &system_path)?;
+    let result = action()?;
+    run(config, When::Post, command, &config_path, &system_path)?;
+
+    Ok(result)
+}
+
+/// Runs the hooks that match, failing if a hook with `abort_on_failure` fails
+fn run(
+    config: &Config,
+    when: When,
+    command: HookCommand,
+    config_path: &Path,
+    system_path: &Path,
+) -> Result<()> {
+    for hook in &config.hooks {
+        if !hook.applies(when, command, system_path)? {
+            continue;
+        }
+
+        if dry_run() {
+            println!("Would run hook `{}`", hook.run);
+            continue;
+        }
+
+        if !SILENT.get().is_some_and(|silent| *silent) {
+            println!("Running hook `{}`", hook.run);
+        }
+
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&hook.run)
+            .env("DOTS_CONFIG_PATH", config_path)
+            .env("DOTS_SYSTEM_PATH", system_path)
+            .status()
+            .with_context(|| format!("running hook `{}`", hook.run))?;
+
+        if !status.success() {
+            if hook.abort_on_failure {
+                bail!("Hook `{}` failed ({status}), aborting", hook.run);
+            }
+
+            eprintln!("Hook `{}` failed ({status})", hook.run);
+        }
+    }
+
+    Ok(())
+}
@@ -1,62 +1,243 @@
 use std::{
-    fs,
-    io::{self},
-    path::Path,
+    fs::{self, Permissions},
+    os::unix::fs::PermissionsExt as _,
+    path::{Path, PathBuf},
+    process,
 };
 
-use anyhow::{Context as _, Result, ensure};
+use anyhow::{Context as _, Result, bail, ensure};
 
 use crate::{
-    add::add,
-    util::{config_path, system_path},
+    backups::{backup, undo_backup},
+    context::Context,
+    copy::{copy_tree, report},
+    hooks::{HookCommand, with_hooks},
+    link,
+    ops::{self, dry_run},
+    secret::{self, decrypt, encrypt},
+    source::with_suffix,
+    util::{config_path, paths_equal, system_path},
 };
 
-/// Imports the given config path from the system path
-pub fn import(cli_path: &Path, copy: bool) -> Result<()> {
-    let config_path = config_path(cli_path)?;
-    let system_path = system_path(cli_path)?;
+/// What `import` did
+pub struct Imported {
+    /// The path in files/ the system path was imported to
+    pub config_path: PathBuf,
+    pub system_path: PathBuf,
+    /// The id of the backup of the system path, if it was replaced with a symlink
+    pub backup: Option<String>,
+}
+
+/// Imports the given config path from the system path.
+///
+/// The system path is only replaced once the copy in files/ has been verified,
+/// and everything is rolled back if replacing it fails.
+pub fn import(
+    context: &Context,
+    cli_path: &Path,
+    copy: bool,
+    preserve_owner: bool,
+    encrypt: bool,
+) -> Result<Imported> {
+    with_hooks(&context.config, HookCommand::Import, cli_path, || {
+        context.escalating(|| import_path(context, cli_path, copy, preserve_owner, encrypt))
+    })
+}
+
+fn import_path(
+    context: &Context,
+    cli_path: &Path,
+    copy: bool,
+    preserve_owner: bool,
+    encrypt: bool,
+) -> Result<Imported> {
+    let config_path = config_path(&context.config, cli_path)?;
+    let system_path = system_path(&context.config, cli_path)?;
+
+    let system_metadata = fs::symlink_metadata(&system_path).with_context(|| {
+        format!(
+            "Failed to get metadata for system path {}",
+            system_path.display()
+        )
+    })?;
+    if system_metadata.is_symlink()
+        && link::target(&system_path).is_ok_and(|destination| destination == config_path)
+    {
+        bail!("{} is already imported", system_path.display());
+    }
+
+    if encrypt {
+        let encrypted_path = import_encrypted(context.secret()?, &config_path, &system_path)?;
+
+        return Ok(Imported {
+            config_path: encrypted_path,
+            system_path,
+            backup: None,
+        });
+    }
 
+    // Never overwrite anything that is already in files/
+    let created_config = if fs::symlink_metadata(&config_path).is_ok() {
+        paths_equal(&config_path, &system_path).with_context(|| {
+            format!(
+                "Config path {} already exists and differs from the system path. Move it away or use `dots add` instead",
+                config_path.display()
+            )
+        })?;
+        false
+    } else {
+        copy_verified(&system_path, &config_path, preserve_owner).with_context(|| {
+            format!(
+                "copying system path ({}) to config path ({})",
+                system_path.display(),
+                config_path.display()
+            )
+        })?;
+        true
+    };
+
+    // With --copy the system path already is an exact copy of the config path
     if copy {
+        return Ok(Imported {
+            config_path,
+            system_path,
+            backup: None,
+        });
+    }
+
+    let backup = replace_with_symlink(&config_path, &system_path, context.config.relative_symlinks)
+        .or_else(|error| {
+            if created_config {
+                ops::remove(&config_path).context("rolling back config path")?;
+            }
+            Err(error)
+        })?;
+
+    Ok(Imported {
+        config_path,
+        system_path,
+        backup: Some(backup),
+    })
+}
+
+/// Copies the source path to a staging path next to the target, verifies the copy and then atomically moves it into place
+fn copy_verified(source: &Path, target: &Path, preserve_owner: bool) -> Result<()> {
+    if dry_run() {
+        return ops::copy(source, target, preserve_owner);
+    }
+
+    let parent = target.parent().context("Failed to get parent of target")?;
+    let file_name = target
+        .file_name()
+        .context("Failed to get file name of target")?
+        .to_str()
+        .context("Failed to convert file name to string")?;
+
+    ops::create_dir_all(parent)?;
+
+    let staging = parent.join(format!(".{file_name}.dots-import-{}", process::id()));
+
+    let result = copy_tree(source, &staging, preserve_owner)
+        .map(|skipped| report(&skipped))
+        .and_then(|()| paths_equal(&staging, source).context("verifying copy"))
+        .and_then(|()| fs::rename(&staging, target).context("moving verified copy into place"));
+
+    // Clean up the staging path if anything went wrong
+    if result.is_err() && fs::symlink_metadata(&staging).is_ok() {
+        ops::remove(&staging).context("removing staging path")?;
+    }
+
+    result
+}
+
+/// Encrypts the system file into files/, and makes it only readable by its owner (like it would be when added).
+/// Returns the path of the encrypted file.
+fn import_encrypted(secret: &[u8], config_path: &Path, system_path: &Path) -> Result<PathBuf> {
+    ensure!(
+        fs::symlink_metadata(system_path)?.is_file(),
+        "Only files can be encrypted"
+    );
+
+    let encrypted_path = with_suffix(config_path, secret::SUFFIX);
+    for path in [config_path, &encrypted_path] {
         ensure!(
-            !system_path.is_dir(),
-            "Only files and symlinks are currently supported with --copy"
+            fs::symlink_metadata(path).is_err(),
+            "Config path {} already exists. Move it away or use `dots add` instead",
+            path.display()
         );
     }
 
-    // Copy system path to config path
-    if system_path.is_dir() {
-        copy_dir(&system_path, &config_path)
-    } else {
-        fs::copy(&system_path, &config_path).map(|_| ())
-    }
-    .with_context(|| {
-        format!(
-            "copying system path ({}) to config path ({})",
+    if dry_run() {
+        println!(
+            "Would encrypt {} to {}",
             system_path.display(),
-            config_path.display()
-        )
-    })?;
+            encrypted_path.display()
+        );
+        println!("Would make {} private", system_path.display());
+        return Ok(encrypted_path);
+    }
 
-    add(cli_path, true, copy)
-}
+    let plaintext =
+        fs::read(system_path).with_context(|| format!("reading {}", system_path.display()))?;
+    let encrypted = encrypt(secret, &plaintext)?;
 
-/// Recursively copies the source directory to the target path
-fn copy_dir(source: impl AsRef<Path>, target: impl AsRef<Path>) -> io::Result<()> {
-    // Create destination
-    fs::create_dir_all(&target)?;
+    let parent = encrypted_path
+        .parent()
+        .context("Failed to get parent of config path")?;
+    ops::create_dir_all(parent)?;
 
-    for entry in fs::read_dir(source)? {
-        let entry = entry?;
+    println!(
+        "Encrypting {} to {}",
+        system_path.display(),
+        encrypted_path.display()
+    );
 
-        let entry_source_path = entry.path();
-        let entry_target_path = target.as_ref().join(entry.file_name());
+    // Stage and verify the encrypted file, like in `copy_verified`
+    let staging = with_suffix(&encrypted_path, &format!(".dots-import-{}", process::id()));
+    let result = fs::write(&staging, &encrypted)
+        .context("writing encrypted file")
+        .and_then(|()| {
+            ensure!(
+                decrypt(secret, &fs::read(&staging)?)? == plaintext,
+                "Decrypted file differs from the system file"
+            );
+            Ok(())
+        })
+        .context("verifying encrypted file")
+        .and_then(|()| {
+            fs::rename(&staging, &encrypted_path).context("moving encrypted file into place")
+        });
 
-        if entry_source_path.is_dir() {
-            copy_dir(entry_source_path, entry_target_path)?;
-        } else {
-            fs::copy(entry_source_path, entry_target_path)?;
-        }
+    if result.is_err() && fs::symlink_metadata(&staging).is_ok() {
+        ops::remove(&staging).context("removing staging path")?;
     }
+    result?;
+
+    fs::set_permissions(system_path, Permissions::from_mode(0o600))
+        .with_context(|| format!("making {} private", system_path.display()))?;
+
+    Ok(encrypted_path)
+}
+
+/// Replaces the system path with a symlink to the config path, restoring the system path on failure.
+/// Returns the id of the backup of the system path.
+fn replace_with_symlink(config_path: &Path, system_path: &Path, relative: bool) -> Result<String> {
+    let id = backup(system_path)?;
+
+    if !dry_run() {
+        println!(
+            "Symlinking {} to {}",
+            config_path.display(),
+            system_path.display(),
+        );
+    }
+
+    link::symlink(config_path, system_path, relative)
+        .with_context(|| format!("creating symlink {}", system_path.display()))
+        .or_else(|error| {
+            undo_backup(&id).context("restoring system path")?;
+            Err(error)
+        })?;
 
-    Ok(())
+    Ok(id)
 }
@@ -0,0 +1,55 @@
+//! Dots as a library, so it can be embedded in other tools (like atem).
+//!
+//! Most operations take a [`Context`], which contains the [`Config`] and the
+//! [`Prompt`] and [`Escalate`] implementations used to interact with the user.
+//!
+//! Some parts are specific to the command line and process-wide: [`SILENT`], [`DRY_RUN`],
+//! [`batch::SKIP`] and [`escalate::HELPER`] are set once by the `dots` binary (everything behaves as if they were off
+//! while they are unset), and [`escalate::Rerun`] reruns the whole process with root privileges and
+//! then exits it. Library callers should leave the statics unset and use [`escalate::Never`] (or
+//! their own [`Escalate`]) instead.
+
+pub mod add;
+pub mod apply;
+pub mod backups;
+pub mod batch;
+pub mod config;
+mod context;
+mod copy;
+pub mod coverage;
+pub mod debug;
+mod diff;
+pub mod escalate;
+pub mod hooks;
+pub mod import;
+mod link;
+pub mod list;
+mod manifest;
+mod ops;
+pub mod output;
+mod placeholders;
+pub mod prompt;
+pub mod relocate;
+pub mod remove;
+mod secret;
+mod source;
+pub mod status;
+mod template;
+mod util;
+
+use std::sync::OnceLock;
+
+pub use add::{Added, add};
+pub use config::Config;
+pub use context::{Context, requires_root};
+pub use escalate::Escalate;
+pub use import::{Imported, import};
+pub use list::{Managed, managed};
+pub use prompt::Prompt;
+pub use remove::{Removed, remove};
+pub use util::{ConfigPath, cli_path, config_path, resolve_config_path, system_path};
+
+/// Whether to suppress any non-primary output
+pub static SILENT: OnceLock<bool> = OnceLock::new();
+/// Whether to only print what would be done, without touching the filesystem
+pub static DRY_RUN: OnceLock<bool> = OnceLock::new();
@@ -0,0 +1,153 @@
+//! Symlinks that can be either absolute or relative to the directory they are in
+
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::ops;
+
+/// The absolute path the symlink points to.
+/// Relative targets are resolved against the parent of the symlink with its symlinks followed, like the OS does.
+/// The path is kept as written when that leads to the same file, so it can be compared to paths in files/.
+pub fn target(link: &Path) -> io::Result<PathBuf> {
+    let target = fs::read_link(link)?;
+
+    if target.is_absolute() {
+        return Ok(target);
+    }
+
+    let parent = link.parent().unwrap_or_else(|| Path::new("/"));
+    let lexical = normalize(&parent.join(&target));
+    let resolved = normalize(&resolve(parent).join(&target));
+
+    if lexical != resolved
+        && fs::canonicalize(&lexical).is_ok_and(|lexical| {
+            fs::canonicalize(&resolved).is_ok_and(|resolved| lexical == resolved)
+        })
+    {
+        return Ok(lexical);
+    }
+
+    Ok(resolved)
+}
+
+/// Creates a symlink at `link` pointing to `target`, relative to the parent of `link` if requested
+pub fn symlink(target: &Path, link: &Path, relative: bool) -> io::Result<()> {
+    if relative {
+        ops::symlink(&relative_to(target, link), link)
+    } else {
+        ops::symlink(target, link)
+    }
+}
+
+/// Whether the symlink points to the target in the requested form (relative or absolute)
+pub fn points_to(link: &Path, target: &Path, relative: bool) -> bool {
+    fs::read_link(link).is_ok_and(|destination| destination.is_relative() == relative)
+        && self::target(link).is_ok_and(|destination| destination == target)
+}
+
+/// The target as seen from the parent of the symlink (with its symlinks followed)
+fn relative_to(target: &Path, link: &Path) -> PathBuf {
+    let target = normalize(target);
+    let parent = resolve(link.parent().unwrap_or_else(|| Path::new("/")));
+
+    let common = target
+        .components()
+        .zip(parent.components())
+        .take_while(|&(target, parent)| target == parent)
+        .count();
+
+    parent
+        .components()
+        .skip(common)
+        .map(|_| Component::ParentDir)
+        .chain(target.components().skip(common))
+        .collect()
+}
+
+/// The directory with all symlinks followed, or just normalized if it doesn't exist (yet)
+fn resolve(dir: &Path) -> PathBuf {
+    fs::canonicalize(dir).unwrap_or_else(|_| normalize(dir))
+}
+
+/// Removes `.` and `..` from the path
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Prefix(_) | Component::RootDir | Component::Normal(_) => {
+                normalized.push(component);
+            }
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, os::unix::fs::symlink, path::Path, process};
+
+    use anyhow::Result;
+
+    use super::{normalize, relative_to, target};
+
+    #[test]
+    fn normalizes() {
+        assert_eq!(normalize(Path::new("/a/./b/../c/")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+    }
+
+    #[test]
+    fn relative_paths() {
+        // These paths dont exist, so they are only normalized
+        let relative = |target: &str, link: &str| relative_to(Path::new(target), Path::new(link));
+
+        assert_eq!(
+            relative(
+                "/dots-test/files/common/dots-test/.bashrc",
+                "/dots-test/.bashrc"
+            ),
+            Path::new("files/common/dots-test/.bashrc")
+        );
+        assert_eq!(
+            relative("/dots-test/files/a", "/dots-test/.config/b/link"),
+            Path::new("../../files/a")
+        );
+        assert_eq!(
+            relative("/dots-test/files/a", "/dots-test/./x/../link"),
+            Path::new("files/a")
+        );
+    }
+
+    #[test]
+    fn follows_symlinks_in_the_parent() -> Result<()> {
+        let root = env::temp_dir().join(format!("dots-link-test-{}", process::id()));
+        fs::create_dir_all(root.join("real/deep"))?;
+        fs::create_dir_all(root.join("files"))?;
+        fs::write(root.join("files/a"), "")?;
+        // root/shortcut -> root/real/deep
+        symlink("real/deep", root.join("shortcut"))?;
+
+        // Resolved against root/real/deep, not root
+        let link = root.join("shortcut/link");
+        let target_path = fs::canonicalize(&root)?.join("files/a");
+        let relative = relative_to(&target_path, &link);
+        symlink(&relative, &link)?;
+
+        let (contents, resolved) = (fs::read_to_string(&link), target(&link));
+        fs::remove_dir_all(&root)?;
+
+        assert_eq!(relative, Path::new("../../files/a"));
+        // The OS resolves the symlink the same way
+        assert_eq!(contents?, "");
+        assert_eq!(resolved?, target_path);
+        Ok(())
+    }
+}
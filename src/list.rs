@@ -1,11 +1,24 @@
-use anyhow::{Context as _, Result};
+use anyhow::{Context as _, Result, anyhow, bail};
+use clap::ValueEnum;
+use serde::Serialize;
 
 use crate::{
-    config::CONFIG,
-    util::{config_path, get_hostname, home, paths_equal, rerun_with_root_args, system_path},
+    SILENT,
+    add::add,
+    backups,
+    batch::run_all,
+    config::Config,
+    context::Context,
+    link,
+    output::{Entry, Format, Kind, print_entries},
+    source,
+    status::State,
+    util::{cli_path, config_path, system_path},
 };
 use std::{
+    fmt::{self, Display, Formatter},
     fs::{self},
+    io::{self, IsTerminal as _},
     iter,
     path::{Path, PathBuf},
     sync::{
@@ -61,18 +74,238 @@ impl PendingPaths {
     }
 }
 
+/// A symlink on the system that was probably made by dots
+pub struct Managed {
+    /// The path in the format taken by `add`
+    pub path: String,
+    /// The location of the symlink
+    pub system_path: PathBuf,
+}
+
 /// Prints all symlinks on the system, that are probably made by dots
-#[expect(clippy::unwrap_used)] // Cant really handle errors in worker threads, we'd unwrap them at some point anyways
-pub fn list(rooted: bool, copy: Option<Vec<String>>) -> Result<()> {
+pub fn list(context: &Context, copy: Option<Vec<String>>, format: Format) -> Result<()> {
     if let Some(items) = copy {
-        return list_copy(items);
+        return list_copy(context, &items, format);
+    }
+
+    let entries = managed(&context.config)?
+        .iter()
+        .map(|managed| Entry::new(context, &managed.path, Kind::Symlink))
+        .collect::<Result<Vec<_>>>()?;
+
+    print_entries(&entries, format, |entry| {
+        // Report which layer won for paths with an elided sub-dir.
+        // Only on a terminal, so the output can be passed to add, remove or import.
+        if entry.path.starts_with('/')
+            && !SILENT.get().is_some_and(|silent| *silent)
+            && io::stdout().is_terminal()
+        {
+            println!("{} (layer {})", entry.path, entry.subdir);
+        } else {
+            println!("{}", entry.path);
+        }
+    })
+}
+
+/// Returns all symlinks on the system, that are probably made by dots
+pub fn managed(config: &Config) -> Result<Vec<Managed>> {
+    let mut managed = Vec::new();
+
+    for (link, target) in crawl(config) {
+        // If the target is in the files/ dir...
+        if let Ok(stripped) = target.strip_prefix(&config.files_path)
+            // ...and was plausibly created by dots...
+            && system_path(config, stripped).is_ok_and(|system_path| system_path == link)
+        {
+            // A single path that can't be converted shouldn't break the whole list
+            match cli_path(config, stripped) {
+                Ok(path) => managed.push(Managed {
+                    path,
+                    system_path: link,
+                }),
+                Err(error) => skip(&link, &error),
+            }
+        }
     }
 
-    // Rerun with root if required
-    if CONFIG.root && !rooted {
-        rerun_with_root_args(&["--rooted"]);
+    managed.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(managed)
+}
+
+/// Why a symlink that was probably made by dots is broken
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Problem {
+    /// The target in files/ doesn't exist anymore
+    Dangling,
+    /// The target is in files/, but dots would have put the symlink somewhere else
+    Misplaced,
+    /// The target is in a files/ directory that doesn't exist anymore (like after moving the repo)
+    Stale,
+}
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Dangling => "dangling",
+            Self::Misplaced => "misplaced",
+            Self::Stale => "stale",
+        })
     }
+}
 
+/// A symlink on the system that was probably made by dots, but is broken
+#[derive(Serialize)]
+pub struct Broken {
+    /// The location of the symlink
+    pub link: PathBuf,
+    pub target: PathBuf,
+    pub problem: Problem,
+    /// The path (in the format taken by `add`) the symlink should be re-added as
+    pub path: String,
+}
+
+/// How to fix broken symlinks
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Fix {
+    /// Remove them
+    Prune,
+    /// Replace them with what `add` would create
+    Readd,
+}
+
+/// Prints all broken symlinks that were probably made by dots, and fixes them if requested
+pub fn list_broken(context: &Context, fix: Option<Fix>, format: Format) -> Result<()> {
+    let broken = broken(&context.config)?;
+
+    print_entries(&broken, format, |broken| {
+        println!(
+            "{:<10} {} -> {}",
+            broken.problem.to_string(),
+            broken.link.display(),
+            broken.target.display()
+        );
+    })?;
+
+    let links: Vec<_> = broken.iter().map(|broken| broken.link.clone()).collect();
+    let find = |link: &Path| {
+        broken
+            .iter()
+            .find(|broken| broken.link == link)
+            .context("Broken symlink went missing")
+    };
+
+    match fix {
+        None => Ok(()),
+        // Removed symlinks are backed up, so pruning can be undone
+        Some(Fix::Prune) => run_all(&links, |link| backups::backup(link).map(|_| ())),
+        Some(Fix::Readd) => run_all(&links, |link| {
+            let broken = find(link)?;
+            let config_path = config_path(&context.config, Path::new(&broken.path))?;
+            if !source::exists(&config_path) {
+                bail!(
+                    "Can't re-add {}, {} doesn't exist",
+                    broken.path,
+                    config_path.display()
+                );
+            }
+
+            // Misplaced symlinks are removed, the others are overwritten by add
+            let force = match broken.problem {
+                Problem::Misplaced => {
+                    backups::backup(link)?;
+                    false
+                }
+                Problem::Dangling | Problem::Stale => true,
+            };
+            add(context, Path::new(&broken.path), force, false, false).map(|_| ())
+        }),
+    }
+}
+
+/// Returns all symlinks on the system that were probably made by dots, but are broken
+pub fn broken(config: &Config) -> Result<Vec<Broken>> {
+    let mut broken = Vec::new();
+    let store = backups::store()?;
+
+    for (link, target) in crawl(config) {
+        // Backed up symlinks are expected to be in the wrong location,
+        // and symlinks inside of files/ are part of the config, not made by dots
+        if link.starts_with(&store) || link.starts_with(&config.files_path) {
+            continue;
+        }
+
+        let target_exists = fs::symlink_metadata(&target).is_ok();
+
+        let (problem, relative) = if let Ok(stripped) = target.strip_prefix(&config.files_path) {
+            let system_path = match system_path(config, stripped) {
+                Ok(system_path) => system_path,
+                Err(error) => {
+                    skip(&link, &error);
+                    continue;
+                }
+            };
+
+            if system_path != link {
+                (Problem::Misplaced, stripped.to_owned())
+            } else if !target_exists {
+                (Problem::Dangling, stripped.to_owned())
+            } else {
+                continue;
+            }
+        } else if !target_exists && let Some(relative) = stale_relative(&link, &target) {
+            (Problem::Stale, relative)
+        } else {
+            continue;
+        };
+
+        match cli_path(config, &relative) {
+            Ok(path) => broken.push(Broken {
+                link,
+                target,
+                problem,
+                path,
+            }),
+            Err(error) => skip(&link, &error),
+        }
+    }
+
+    broken.sort_unstable_by(|a, b| a.link.cmp(&b.link));
+
+    Ok(broken)
+}
+
+/// Reports a symlink that is skipped because its paths can't be converted
+fn skip(link: &Path, error: &anyhow::Error) {
+    eprintln!("Skipping {}: {error:#}", link.display());
+}
+
+/// The path relative to the old files/ directory, if the target looks like it was in one.
+/// This is the case if the target ends with the location of the symlink, preceded by a sub-dir.
+fn stale_relative(link: &Path, target: &Path) -> Option<PathBuf> {
+    let link = link.strip_prefix("/").ok()?;
+    if !target.is_absolute() || !target.ends_with(link) {
+        return None;
+    }
+
+    // The target without the location of the symlink: (old files/)/(sub-dir)
+    let prefix = target.ancestors().nth(link.components().count())?;
+    let subdir = prefix.file_name()?;
+
+    // The old files/ directory can't be the root
+    if prefix.parent()? == Path::new("/") {
+        return None;
+    }
+
+    Some(Path::new(subdir).join(link))
+}
+
+/// Returns all symlinks below `list_paths` and their targets
+///
+/// # Panics
+/// If reading a directory fails in one of the worker threads
+#[expect(clippy::unwrap_used)] // Cant really handle errors in worker threads, we'd unwrap them at some point anyways
+pub fn crawl(config: &Config) -> Vec<(PathBuf, PathBuf)> {
     let threads = thread::available_parallelism().map_or(12, Into::into);
 
     // Set up pending paths
@@ -83,12 +316,14 @@ pub fn list(rooted: bool, copy: Option<Vec<String>>) -> Result<()> {
     let pending_paths: Vec<_> = iter::repeat_with(PendingPaths::default)
         .take(threads)
         .collect();
-    for (index, path) in CONFIG.list_paths.iter().enumerate() {
+    for (index, path) in config.list_paths.iter().enumerate() {
         pending_paths[index].push(path.into());
     }
 
     let pending = AtomicUsize::new(0);
 
+    let found = Mutex::new(Vec::new());
+
     // The borrow checker wont let us just capture i in 'for _ in ...', so we have to do this
     let index = AtomicUsize::new(0);
 
@@ -104,7 +339,7 @@ pub fn list(rooted: bool, copy: Option<Vec<String>>) -> Result<()> {
                         // Or try stealing a path from another thread's queue
                         .or_else(|| try_steal_path(&pending_paths, my_index))
                     {
-                        process_path(&pending_paths, &pending, my_index, &path)
+                        process_path(config, &pending_paths, &pending, &found, my_index, &path)
                             .with_context(|| format!("Failed to process path {}", path.display()))
                             .unwrap();
                         continue;
@@ -122,7 +357,7 @@ pub fn list(rooted: bool, copy: Option<Vec<String>>) -> Result<()> {
         }
     });
 
-    Ok(())
+    found.into_inner().unwrap()
 }
 
 /// Try to steal a pending path from another thread.
@@ -173,8 +408,10 @@ fn try_steal_path(pending_paths: &[PendingPaths], my_index: usize) -> Option<Pat
 }
 
 fn process_path(
+    config: &Config,
     pending_paths: &[PendingPaths],
     pending: &AtomicUsize,
+    found: &Mutex<Vec<(PathBuf, PathBuf)>>,
     thread_index: usize,
     path: &Path,
 ) -> Result<()> {
@@ -194,37 +431,17 @@ fn process_path(
             if file_type.is_symlink() {
                 // get the entries target
                 // Dont panic on failure
-                if let Ok(target) = fs::read_link(&entry_path) {
-                    // If the target is in the files/ dir...
-                    if let Ok(stripped) = target.strip_prefix(&CONFIG.files_path)
-                // ...and was plausibly created by dots...
-                && system_path(stripped)? == dir_entry.path()
-                    {
-                        // Convert to a string, so strip_prefix() doesnt remove leading slashes
-                        if let Some(str) = stripped.to_str() {
-                            let str = str.replace(&home()?, "/{home}");
-
-                            let formatted = str
-                                .strip_prefix(&CONFIG.default_subdir) // If the subdir is the default one, remove it
-                                .map(Into::into)
-                                // If the subdir is the current hostname, replace it with {hostname}
-                                .or_else(|| {
-                                    str.strip_prefix(&get_hostname().ok()?)
-                                        .map(|str| format!("{{hostname}}{str}"))
-                                })
-                                .unwrap_or(str);
-
-                            println!("{formatted}");
-                        }
-                    }
+                if let Ok(target) = link::target(&entry_path) {
+                    found
+                        .lock()
+                        .map_err(|_| anyhow!("Another thread panicked"))?
+                        .push((entry_path, target));
                 }
             } else if file_type.is_dir() {
-                let path = dir_entry.path();
-
                 // Filter out ignored paths
-                if !CONFIG.ignore_paths.contains(&path) {
+                if !config.ignore_paths.contains(&entry_path) {
                     // Recurse into the dir
-                    pending_paths[thread_index].push(path);
+                    pending_paths[thread_index].push(entry_path);
                 }
             }
         }
@@ -236,23 +453,62 @@ fn process_path(
     Ok(())
 }
 
-fn list_copy(items: Vec<String>) -> Result<()> {
-    for item in items {
-        let path = Path::new(&item);
+/// Prints the items that are on the system and equal to the config.
+/// Machine readable formats include all items along with their state.
+fn list_copy(context: &Context, items: &[String], format: Format) -> Result<()> {
+    let entries = items
+        .iter()
+        .map(|item| Entry::new(context, item, Kind::Copy))
+        .collect::<Result<Vec<_>>>()?;
+
+    print_entries(&entries, format, |entry| {
+        if entry.state.as_ref().is_some_and(State::in_sync) {
+            println!("{}", entry.path);
+        }
+    })
+}
 
-        let config_path = config_path(path)?;
-        let system_path = system_path(path)?;
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
 
-        // If path exists on the system
-        if fs::exists(path).with_context(
-            || format!("checking if the path {} already exists", path.display()),
-            // And is equal to the one in the config
-        )? && paths_equal(&config_path, &system_path).is_ok()
-        {
-            // Print it
-            println!("{item}");
-        }
+    use super::stale_relative;
+
+    fn stale(link: &str, target: &str) -> Option<String> {
+        stale_relative(Path::new(link), Path::new(target))
+            .map(|relative| relative.display().to_string())
     }
 
-    Ok(())
+    #[test]
+    fn finds_the_path_in_the_old_files_directory() {
+        assert_eq!(
+            stale("/home/user/.bashrc", "/old/files/common/home/user/.bashrc").as_deref(),
+            Some("common/home/user/.bashrc")
+        );
+        assert_eq!(
+            stale("/etc/hosts", "/home/user/dots/files/vm/etc/hosts").as_deref(),
+            Some("vm/etc/hosts")
+        );
+    }
+
+    #[test]
+    fn ignores_unrelated_targets() {
+        // The target doesn't end with the location of the symlink
+        assert_eq!(
+            stale("/home/user/.bashrc", "/old/files/common/.bashrc"),
+            None
+        );
+        // Relative targets
+        assert_eq!(
+            stale("/home/user/.bashrc", "files/common/home/user/.bashrc"),
+            None
+        );
+        // The files/ directory would be the root
+        assert_eq!(
+            stale("/home/user/.bashrc", "/common/home/user/.bashrc"),
+            None
+        );
+        // There is no room for a sub-dir
+        assert_eq!(stale("/home/user/.bashrc", "/home/user/.bashrc"), None);
+    }
 }
@@ -1,72 +1,134 @@
-#![feature(let_chains)]
-
-mod add;
-mod config;
-mod debug;
-mod import;
-mod list;
-mod remove;
-mod util;
-
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use std::{
-    io::{self, ErrorKind},
-    path::PathBuf,
-    sync::OnceLock,
+use atem_dots::{
+    Config, Context, DRY_RUN, SILENT, add, apply, backups,
+    batch::{self, Against},
+    config, coverage,
+    debug::{self, Debug},
+    escalate::{Backend, HELPER, Mode, Operation, Rerun, rerun_with_root, rerun_with_root_args},
+    import,
+    list::{self, Fix},
+    output::Format,
+    prompt::{Policy, Terminal},
+    relocate, remove, requires_root, status,
 };
-use util::rerun_with_root;
+use clap::{Parser, Subcommand};
+use std::{path::PathBuf, process::exit};
 
 #[derive(Parser, Debug)]
 #[command(name = "dots")]
+#[expect(clippy::struct_excessive_bools)] // Command line flags are bools
 struct Cli {
     #[arg(short, long)]
     /// Only output the found items
     silent: bool,
+    #[arg(short = 'n', long, global = true)]
+    /// Only print what would be removed, copied, created or symlinked, without touching the filesystem.
+    /// Questions are still asked, as the answers decide what would be done
+    dry_run: bool,
+    #[arg(long, global = true, value_enum, default_value_t)]
+    /// The output format of `list` and `debug`
+    format: Format,
+    #[arg(long, global = true, conflicts_with_all = ["no", "non_interactive"])]
+    /// Answer yes to every question
+    yes: bool,
+    #[arg(long, global = true, conflicts_with = "non_interactive")]
+    /// Answer no to every question
+    no: bool,
+    #[arg(long, global = true)]
+    /// Never ask questions, use the safe answer instead (which is never to overwrite anything)
+    non_interactive: bool,
+    #[arg(long, global = true, value_name = "PATH")]
+    /// Use the config at the given path (default: `$DOTS_CONFIG`, `$XDG_CONFIG_HOME/dots` or ~/.config/dots)
+    config: Option<PathBuf>,
+    #[arg(long, global = true, hide = true, value_name = "PATH")]
+    /// A path that was already handled before rerunning with root privileges (added by dots itself)
+    done: Vec<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Add the given path to the system
+    /// Add the given paths to the system
     #[command(arg_required_else_help = true)]
     Add {
         /// Format: (sub-dir of ~/.config/rebos/files)/(path to symlink).
         /// If the path is absolute, it is automatically prepended with <`DEFAULT_SUBDIR`>.
         /// "{hostname}" can be used as a placeholder for the actual hostname of the system.
         /// "{home}" can be used as a placeholder for the home dir of the user.
-        path: PathBuf,
+        /// Glob patterns are matched against the files/ directory.
+        #[arg(required_unless_present = "from_file")]
+        paths: Vec<PathBuf>,
+
+        #[arg(long, value_name = "FILE")]
+        /// Also read paths from the file (one per line, "-" for stdin)
+        from_file: Option<PathBuf>,
 
         #[arg(long)]
         /// Copy instead of symlink the path
         copy: bool,
 
+        #[arg(long, conflicts_with = "copy")]
+        /// Create a symlink relative to the directory it is in, instead of an absolute one
+        relative: bool,
+
         #[arg(short, long)]
         /// Overwrite the destination without asking
         force: bool,
     },
-    /// Remove the given path from the system (does not remove the files the path points to, only the symlink)
+    /// Remove the given paths from the system (does not remove the files the path points to, only the symlink)
     #[command(arg_required_else_help = true)]
     Remove {
         /// Format: (sub-dir of ~/.config/rebos/files}/{path to symlink)
         /// If the path is absolute, it is assumed to already be the path to remove.
         /// "{hostname}" can be used as a placeholder for the actual hostname of the system.
         /// "{home}" can be used as a placeholder for the home dir of the user.
-        path: PathBuf,
+        /// Glob patterns are matched against the files/ directory.
+        #[arg(required_unless_present = "from_file")]
+        paths: Vec<PathBuf>,
+
+        #[arg(long, value_name = "FILE")]
+        /// Also read paths from the file (one per line, "-" for stdin)
+        from_file: Option<PathBuf>,
+
+        #[arg(long)]
+        /// The path was added using --copy, so remove it if it is unchanged
+        copy: bool,
+
+        #[arg(short, long)]
+        /// Remove the path even if it wasn't made by dots (it is moved into the backup store)
+        force: bool,
+
+        #[arg(long, conflicts_with = "copy")]
+        /// Replace the symlink with a copy of the files it points to
+        restore: bool,
     },
-    /// Import the given path from the system
+    /// Import the given paths from the system
     #[command(arg_required_else_help = true)]
     Import {
         /// Format: (sub-dir of ~/.config/rebos/files)/(path to symlink).
         /// If the path is absolute, it is automatically prepended with <`DEFAULT_SUBDIR`>.
         /// "{hostname}" can be used as a placeholder for the actual hostname of the system.
         /// "{home}" can be used as a placeholder for the home dir of the user.
-        path: PathBuf,
+        /// Glob patterns are matched against the system.
+        #[arg(required_unless_present = "from_file")]
+        paths: Vec<PathBuf>,
+
+        #[arg(long, value_name = "FILE")]
+        /// Also read paths from the file (one per line, "-" for stdin)
+        from_file: Option<PathBuf>,
 
         #[arg(long)]
         /// Copy instead of symlink the path
         copy: bool,
+
+        #[arg(long)]
+        /// Preserve the owner and group of the imported files (usually requires root)
+        preserve_owner: bool,
+
+        #[arg(long, conflicts_with = "preserve_owner")]
+        /// Encrypt the file in files/ (implies --copy)
+        encrypt: bool,
     },
     /// Outputs a list of all symlinks on the system that are probably made by dots
     List {
@@ -74,14 +136,66 @@ enum Commands {
         /// Assume that the current user is root
         rooted: bool,
 
-        #[arg(long, trailing_var_arg = true, num_args(1..))]
+        #[arg(long, trailing_var_arg = true, num_args(1..), conflicts_with = "broken")]
         copy: Option<Vec<String>>,
+
+        #[arg(long)]
+        /// List symlinks into files/ that are dangling, in the wrong location, or point into an old files/ directory
+        broken: bool,
+
+        #[arg(long, value_enum, requires = "broken")]
+        /// Fix the broken symlinks
+        fix: Option<Fix>,
     },
+    /// Add every path in the manifest (the config path with .manifest appended) to the system and report managed paths missing from it
+    Apply {
+        #[arg(short, long)]
+        /// Overwrite destinations without asking
+        force: bool,
+    },
+    /// Show the state of every path in the manifest, exits with 1 if any of them are out of sync
+    Status,
+    /// Show which paths in the sub-dirs of files/ that apply to this host are deployed on the system
+    CheckCoverage {
+        #[arg(long)]
+        /// Only print the paths that aren't deployed, in the format taken by `add`
+        missing_only: bool,
+    },
+    /// Point all symlinks into the old files/ directory at the new one (after moving it), and update `files_path` in the config
+    #[command(arg_required_else_help = true)]
+    Relocate {
+        /// The old location of the files/ directory
+        old: String,
+        /// The new location of the files/ directory
+        new: String,
+    },
+    /// Manage the backups of paths that were overwritten
+    #[command(subcommand)]
+    Backups(BackupsCommands),
     /// Debugging commands
     #[command(subcommand)]
     Debug(DebugCommands),
     /// Interactively creates the config file
     Config,
+    /// Runs a filesystem operation that requires root privileges (used by the helper escalation mode)
+    #[command(name = "__helper", hide = true, subcommand)]
+    Helper(Operation),
+}
+
+#[derive(Subcommand, Debug)]
+enum BackupsCommands {
+    /// List all backups
+    List,
+    /// Restore the backup with the given id to its original location
+    #[command(arg_required_else_help = true)]
+    Restore {
+        /// The id of the backup, as shown by `dots backups list`
+        id: String,
+
+        #[arg(short, long)]
+        /// Overwrite the original location without asking
+        force: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -104,8 +218,6 @@ enum DebugCommands {
     },
 }
 
-static SILENT: OnceLock<bool> = OnceLock::new();
-
 #[expect(clippy::expect_used)]
 fn main() -> Result<()> {
     let args = Cli::parse();
@@ -113,20 +225,153 @@ fn main() -> Result<()> {
     SILENT
         .set(args.silent)
         .expect("SILENT shouldnt be already initialized");
+    DRY_RUN
+        .set(args.dry_run)
+        .expect("DRY_RUN shouldnt be already initialized");
+    config::CONFIG_PATH
+        .set(args.config)
+        .expect("CONFIG_PATH shouldnt be already initialized");
+    batch::SKIP
+        .set(args.done)
+        .expect("SKIP shouldnt be already initialized");
 
-    match args.command {
-        Commands::Add { path, force, copy } => add::add(&path, force, copy),
-        Commands::Remove { path } => remove::remove(&path),
-        Commands::Import { path, copy } => import::import(&path, copy),
-        Commands::List { rooted, copy } => list::list(rooted, copy),
-        Commands::Debug(debug_command) => debug::debug(debug_command),
-        Commands::Config => config::Config::setup(),
-    }
-    .inspect_err(|error| {
-        if let Some(io_error) = error.root_cause().downcast_ref::<io::Error>() {
-            if io_error.kind() == ErrorKind::PermissionDenied {
-                rerun_with_root(&format!("{error}")); // just formatting the error should be fine for handling context
+    let policy = if args.yes {
+        Policy::Yes
+    } else if args.no {
+        Policy::No
+    } else if args.non_interactive {
+        Policy::NonInteractive
+    } else {
+        Policy::Ask
+    };
+    // The helper already has root privileges
+    let is_helper = matches!(args.command, Commands::Helper(_));
+
+    run(args.command, args.format, policy).inspect_err(|error| {
+        if !is_helper && requires_root(error) {
+            let backend =
+                Config::load().map_or_else(|_| Backend::default(), |config| config.escalation);
+
+            if backend != Backend::None {
+                rerun_with_root(backend, &format!("{error}")); // just formatting the error should be fine for handling context
             }
         }
     })
 }
+
+/// Runs the command
+#[expect(clippy::expect_used, clippy::too_many_lines)] // One match arm per command
+fn run(command: Commands, format: Format, policy: Policy) -> Result<()> {
+    let terminal = || Box::new(Terminal { policy });
+
+    // The config is only loaded by the commands that need it
+    let context = || -> Result<Context> {
+        let config = Config::load()?;
+
+        if config.escalation_mode == Mode::Helper {
+            HELPER
+                .set(config.escalation)
+                .expect("HELPER shouldnt be already initialized");
+        }
+
+        let escalation = config.escalation;
+        Ok(Context::new(
+            config,
+            terminal(),
+            Box::new(Rerun(escalation)),
+        ))
+    };
+
+    match command {
+        Commands::Add {
+            paths,
+            from_file,
+            force,
+            copy,
+            relative,
+        } => {
+            let context = context()?;
+            batch::run(
+                &context.config,
+                paths,
+                from_file.as_deref(),
+                Against::Files,
+                |path| add(&context, path, force, copy, relative).map(|_| ()),
+            )
+        }
+        Commands::Remove {
+            paths,
+            from_file,
+            copy,
+            force,
+            restore,
+        } => {
+            let context = context()?;
+            batch::run(
+                &context.config,
+                paths,
+                from_file.as_deref(),
+                Against::Files,
+                |path| remove(&context, path, copy, force, restore).map(|_| ()),
+            )
+        }
+        Commands::Import {
+            paths,
+            from_file,
+            copy,
+            preserve_owner,
+            encrypt,
+        } => {
+            let context = context()?;
+            batch::run(
+                &context.config,
+                paths,
+                from_file.as_deref(),
+                Against::System,
+                |path| import(&context, path, copy, preserve_owner, encrypt).map(|_| ()),
+            )
+        }
+        Commands::List {
+            rooted,
+            copy,
+            broken,
+            fix,
+        } => {
+            let context = context()?;
+
+            // Rerun with root if required
+            if context.config.root && !rooted {
+                rerun_with_root_args(context.config.escalation, &["--rooted"]);
+            }
+
+            if broken {
+                return list::list_broken(&context, fix, format);
+            }
+
+            list::list(&context, copy, format)
+        }
+        Commands::Apply { force } => apply::apply(&context()?, force),
+        Commands::Status => {
+            if !status::status(&context()?)? {
+                exit(1);
+            }
+            Ok(())
+        }
+        Commands::CheckCoverage { missing_only } => {
+            coverage::print_coverage(&context()?, missing_only)
+        }
+        Commands::Relocate { old, new } => relocate::relocate(&context()?, &old, &new),
+        Commands::Backups(BackupsCommands::List) => backups::list(),
+        Commands::Backups(BackupsCommands::Restore { id, force }) => {
+            backups::restore(&*terminal(), &id, force)
+        }
+        Commands::Debug(DebugCommands::ConfigPath { path }) => {
+            debug::debug(&context()?, Debug::ConfigPath, &path, format)
+        }
+        Commands::Debug(DebugCommands::SystemPath { path }) => {
+            debug::debug(&context()?, Debug::SystemPath, &path, format)
+        }
+        Commands::Config => Config::setup(&*terminal()),
+        Commands::Helper(operation) => operation.run(),
+    }
+}
@@ -0,0 +1,78 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context as _, Result, bail};
+
+use crate::config::Config;
+
+/// A path that should be present on the system
+pub struct Entry {
+    /// The path, in the same format as taken by `add`
+    pub path: PathBuf,
+    /// Whether the path should be copied instead of symlinked
+    pub copy: bool,
+    /// Whether the symlink should be relative
+    pub relative: bool,
+}
+
+/// The location of the manifest file, which is next to the config file
+pub fn path(config: &Config) -> PathBuf {
+    let mut path = config.path.clone().into_os_string();
+    path.push(".manifest");
+    path.into()
+}
+
+/// Loads the entries of the manifest.
+///
+/// Every non-empty line that doesn't start with '#' is a path, optionally followed by `--copy` and/or `--relative`.
+pub fn load(config: &Config) -> Result<Vec<Entry>> {
+    let path = path(config);
+
+    let string = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "Failed to read manifest at {}. Create it and list one path per line.",
+            path.display()
+        )
+    })?;
+
+    let mut entries = Vec::new();
+
+    for (line, number) in string.lines().zip(1_usize..) {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut path = line;
+        let (mut copy, mut relative) = (false, false);
+        loop {
+            if let Some(rest) = path.strip_suffix("--copy") {
+                copy = true;
+                path = rest.trim_end();
+            } else if let Some(rest) = path.strip_suffix("--relative") {
+                relative = true;
+                path = rest.trim_end();
+            } else {
+                break;
+            }
+        }
+
+        if path.is_empty() || path.starts_with("--") {
+            bail!("Invalid manifest entry on line {number}: {line}");
+        }
+
+        if copy && relative {
+            bail!(
+                "Invalid manifest entry on line {number}, --copy and --relative can't be combined: {line}"
+            );
+        }
+
+        entries.push(Entry {
+            path: path.into(),
+            copy,
+            relative,
+        });
+    }
+
+    Ok(entries)
+}
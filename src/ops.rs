@@ -0,0 +1,175 @@
+//! Filesystem operations that respect `--dry-run`
+//!
+//! In dry-run mode, the operations only print what they would do.
+//! In the helper escalation mode, operations that fail because they require root privileges are run in the helper.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    os::unix::fs::symlink as unix_symlink,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+
+use crate::{
+    DRY_RUN,
+    context::requires_root,
+    copy::{copy_tree, report},
+    escalate::{HELPER, Operation},
+};
+
+/// Whether dots is running in dry-run mode
+pub fn dry_run() -> bool {
+    DRY_RUN.get().is_some_and(|dry_run| *dry_run)
+}
+
+/// Removes the path, recursing into directories (but not following symlinks)
+pub fn remove(path: &Path) -> Result<()> {
+    if dry_run() {
+        println!("Would remove {}", path.display());
+        return Ok(());
+    }
+
+    delegate(&Operation::Remove { path: path.into() }, || {
+        if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()) {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+        .with_context(|| format!("removing path {}", path.display()))
+    })
+}
+
+/// Creates the directory and all of its missing parents
+pub fn create_dir_all(path: &Path) -> Result<()> {
+    if dry_run() {
+        print_missing_dirs(path);
+        return Ok(());
+    }
+
+    delegate(&Operation::CreateDirAll { path: path.into() }, || {
+        fs::create_dir_all(path).with_context(|| format!("creating directory {}", path.display()))
+    })
+}
+
+/// Creates a symlink at `link` pointing to `target`
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    if dry_run() {
+        // The caller would create the missing parents after getting NotFound
+        if let Some(parent) = link.parent() {
+            print_missing_dirs(parent);
+        }
+
+        println!("Would symlink {} to {}", target.display(), link.display());
+        return Ok(());
+    }
+
+    let result = unix_symlink(target, link);
+
+    match HELPER.get() {
+        Some(&backend)
+            if result
+                .as_ref()
+                .is_err_and(|error| error.kind() == ErrorKind::PermissionDenied) =>
+        {
+            Operation::Symlink {
+                target: target.into(),
+                link: link.into(),
+            }
+            .run_in_helper(backend)
+            .map_err(io::Error::other)
+        }
+        _ => result,
+    }
+}
+
+/// Moves the source path to the target path
+pub fn rename(source: &Path, target: &Path) -> io::Result<()> {
+    if dry_run() {
+        println!("Would move {} to {}", source.display(), target.display());
+        return Ok(());
+    }
+
+    let result = fs::rename(source, target);
+
+    match HELPER.get() {
+        Some(&backend)
+            if result
+                .as_ref()
+                .is_err_and(|error| error.kind() == ErrorKind::PermissionDenied) =>
+        {
+            Operation::Rename {
+                source: source.into(),
+                target: target.into(),
+            }
+            .run_in_helper(backend)
+            .map_err(io::Error::other)
+        }
+        _ => result,
+    }
+}
+
+/// Recursively copies the source path to the target path, printing anything that had to be skipped
+pub fn copy(source: &Path, target: &Path, preserve_owner: bool) -> Result<()> {
+    if dry_run() {
+        println!("Would copy {} to {}", source.display(), target.display());
+        return Ok(());
+    }
+
+    delegate(
+        &Operation::Copy {
+            preserve_owner,
+            source: source.into(),
+            target: target.into(),
+        },
+        || {
+            report(&copy_tree(source, target, preserve_owner)?);
+            Ok(())
+        },
+    )
+}
+
+/// Writes the contents to the path, replacing it if it exists
+pub fn write(path: &Path, contents: &str) -> Result<()> {
+    if dry_run() {
+        println!("Would write {}:\n{contents}", path.display());
+        return Ok(());
+    }
+
+    delegate(
+        &Operation::Write {
+            path: path.into(),
+            contents: contents.into(),
+        },
+        || fs::write(path, contents).with_context(|| format!("writing {}", path.display())),
+    )
+}
+
+/// Runs the action, running the operation in the helper instead if it is enabled and the action fails because it requires root privileges
+fn delegate(operation: &Operation, action: impl FnOnce() -> Result<()>) -> Result<()> {
+    let result = action();
+
+    match HELPER.get() {
+        Some(&backend) if result.as_ref().is_err_and(requires_root) => {
+            operation.run_in_helper(backend)
+        }
+        _ => result,
+    }
+}
+
+/// Prints the directories that `fs::create_dir_all()` would create, from the outermost to the innermost
+fn print_missing_dirs(path: &Path) {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|ancestor| {
+            !ancestor.as_os_str().is_empty() && fs::symlink_metadata(ancestor).is_err()
+        })
+        .map(Into::into)
+        .collect();
+    missing.reverse();
+
+    for dir in missing {
+        println!("Would create directory {}", dir.display());
+    }
+}
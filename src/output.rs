@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    context::Context,
+    source::generated_from,
+    status::{State, state},
+    util::{ConfigPath, resolve_config_path, system_path},
+};
+
+/// The format of the output of `list` and `debug`
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Format {
+    /// Human readable text
+    #[default]
+    Human,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+/// How a path is put on the system
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Symlink,
+    Copy,
+}
+
+/// Everything dots knows about a path
+#[derive(Serialize)]
+pub struct Entry {
+    /// The path in the format taken by `add`
+    pub path: String,
+    pub config_path: PathBuf,
+    pub system_path: PathBuf,
+    /// The sub-dir of files/ the config path is in (the layer that won)
+    pub subdir: String,
+    pub kind: Kind,
+    /// Unset if only the paths were resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<State>,
+}
+impl Entry {
+    pub fn new(context: &Context, path: &str, kind: Kind) -> Result<Self> {
+        let mut entry = Self::paths(context, path, kind)?;
+
+        entry.state = Some(state(
+            context,
+            &entry.config_path,
+            &entry.system_path,
+            matches!(kind, Kind::Copy),
+        )?);
+
+        Ok(entry)
+    }
+    /// Resolves the paths without computing the state (which may require rendering templates or decrypting secrets)
+    pub fn paths(context: &Context, path: &str, kind: Kind) -> Result<Self> {
+        let ConfigPath {
+            path: config_path,
+            layer: subdir,
+        } = resolve_config_path(&context.config, Path::new(path))?;
+        let system_path = system_path(&context.config, Path::new(path))?;
+
+        // Generated paths (like templates) are always copied
+        let kind = if generated_from(&config_path).is_some() {
+            Kind::Copy
+        } else {
+            kind
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            config_path,
+            system_path,
+            subdir,
+            kind,
+            state: None,
+        })
+    }
+}
+
+/// States are serialized as they are displayed
+impl Serialize for State {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Prints the entries in the given format, using `human` to print each entry for human readable output
+pub fn print_entries<T: Serialize>(
+    entries: &[T],
+    format: Format,
+    human: impl Fn(&T),
+) -> Result<()> {
+    match format {
+        Format::Human => entries.iter().for_each(human),
+        Format::Json => {
+            println!("{}", serde_json::to_string_pretty(entries)?);
+        }
+        Format::Ndjson => {
+            for entry in entries {
+                println!("{}", serde_json::to_string(entry)?);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! Placeholders that can be used in paths, like "{home}" or "{env:EDITOR}"
+
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+
+use crate::util::{get_hostname, home, uid};
+
+/// Replaces all placeholders in the string, using the given variables.
+///
+/// Built-in placeholders: {home}, {hostname}, {user}, {uid}, {os}, {arch} and {env:NAME}.
+/// Any other name is looked up in the variables. Braces that aren't placeholders (like in "{uuid}")
+/// are kept as they are, and "{{" is a literal "{" (so "{{home}" is the literal "{home}").
+///
+/// A '/' directly before "{home}" is merged with the leading '/' of the home dir,
+/// so "/{home}/file" and "{home}/file" are the same path.
+pub fn expand_with(string: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut expanded = String::with_capacity(string.len());
+    let mut rest = string;
+
+    while let Some((before, after)) = rest.split_once('{') {
+        expanded.push_str(before);
+
+        if let Some(after) = after.strip_prefix('{') {
+            expanded.push('{');
+            rest = after;
+            continue;
+        }
+
+        let Some((name, after)) = after.split_once('}') else {
+            // Unclosed braces are kept
+            expanded.push('{');
+            rest = after;
+            continue;
+        };
+
+        let Some(value) = lookup(name, vars).with_context(|| format!("expanding '{string}'"))?
+        else {
+            // Unknown names are kept
+            expanded.push('{');
+            expanded.push_str(name);
+            expanded.push('}');
+            rest = after;
+            continue;
+        };
+
+        if name == "home" && expanded.ends_with('/') {
+            expanded.push_str(value.strip_prefix('/').unwrap_or(&value));
+        } else {
+            expanded.push_str(&value);
+        }
+
+        rest = after;
+    }
+
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// The value of the placeholder with the given name
+pub fn value(name: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    lookup(name, vars)?.with_context(|| format!("Unknown placeholder {{{name}}}"))
+}
+
+/// The value of the placeholder with the given name, if there is one with that name
+fn lookup(name: &str, vars: &BTreeMap<String, String>) -> Result<Option<String>> {
+    Ok(Some(match name {
+        "home" => home()?,
+        "hostname" => get_hostname()?,
+        "user" => env::var("USER")
+            .or_else(|_| env::var("LOGNAME"))
+            .context("Failed to get USER or LOGNAME env variable")?,
+        "uid" => uid()?.to_string(),
+        "os" => env::consts::OS.to_owned(),
+        "arch" => env::consts::ARCH.to_owned(),
+        other => {
+            if let Some(variable) = other.strip_prefix("env:") {
+                env::var(variable)
+                    .with_context(|| format!("Failed to get {variable} env variable"))?
+            } else {
+                return Ok(vars.get(other).cloned());
+            }
+        }
+    }))
+}
+
+/// Replaces the home dir at the start of the path (relative to /) with "{home}".
+///
+/// Only {home} and {hostname} (see `collapse_subdir()`) are collapsed,
+/// as the values of the other placeholders could also be part of the path by coincidence.
+#[expect(clippy::literal_string_with_formatting_args)]
+pub fn collapse_home(path: &Path) -> Result<PathBuf> {
+    let home = home()?;
+    let home = home.trim_start_matches('/');
+
+    Ok(match path.strip_prefix(home) {
+        Ok(stripped) if !home.is_empty() => Path::new("{home}").join(stripped),
+        _ => path.into(),
+    })
+}
+
+/// Replaces the sub-dir with "{hostname}", if it is the hostname
+pub fn collapse_subdir(subdir: &str) -> String {
+    if get_hostname().is_ok_and(|hostname| hostname == subdir) {
+        "{hostname}".into()
+    } else {
+        subdir.into()
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::literal_string_with_formatting_args)] // Placeholders look like formatting args
+mod tests {
+    use std::{collections::BTreeMap, env};
+
+    use anyhow::Result;
+
+    use super::expand_with;
+
+    fn expand(string: &str) -> Result<String> {
+        let vars = [("mon".to_owned(), "DP-1".to_owned())].into();
+        expand_with(string, &vars)
+    }
+
+    #[test]
+    fn expands_placeholders() -> Result<()> {
+        assert_eq!(expand("{mon}.conf")?, "DP-1.conf");
+        assert_eq!(
+            expand("{os}-{arch}")?,
+            format!("{}-{}", env::consts::OS, env::consts::ARCH)
+        );
+        assert_eq!(expand("{env:HOME}")?, env::var("HOME")?);
+        assert_eq!(expand("no placeholders")?, "no placeholders");
+        Ok(())
+    }
+
+    #[test]
+    fn merges_the_slash_before_home() -> Result<()> {
+        let home = env::var("HOME")?;
+
+        assert_eq!(expand("/{home}/file")?, format!("{home}/file"));
+        assert_eq!(expand("{home}/file")?, format!("{home}/file"));
+        assert_eq!(expand("common/{home}/file")?, format!("common{home}/file"));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_braces_that_arent_placeholders() -> Result<()> {
+        assert_eq!(
+            expand("/{home}/.mozilla/{ec8030f7-c20a-464f-9b0e-13a3a9e97384}")?,
+            format!(
+                "{}/.mozilla/{{ec8030f7-c20a-464f-9b0e-13a3a9e97384}}",
+                env::var("HOME")?
+            )
+        );
+        assert_eq!(expand("{unclosed")?, "{unclosed");
+        // The name reaches up to the first closing brace
+        assert_eq!(expand("a{b{mon}")?, "a{b{mon}");
+        Ok(())
+    }
+
+    #[test]
+    fn double_braces_are_literal() -> Result<()> {
+        assert_eq!(expand("{{home}")?, "{home}");
+        assert_eq!(expand("{{{mon}")?, "{DP-1");
+        Ok(())
+    }
+
+    #[test]
+    fn missing_env_variables_fail() {
+        assert!(
+            expand_with("{env:DOTS_TEST_UNSET_VARIABLE}", &BTreeMap::new()).is_err(),
+            "unset variable"
+        );
+    }
+}
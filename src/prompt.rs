@@ -0,0 +1,108 @@
+//! Asking the user questions
+
+use std::io::{Write as _, stdin, stdout};
+
+use anyhow::{Context as _, Result, bail};
+use clap::ValueEnum;
+
+use crate::SILENT;
+
+/// How dots asks the user questions, supplied by the caller
+pub trait Prompt {
+    /// Asks the user a yes/no question.
+    /// `default` is the safe answer, which is used when the question can't be asked.
+    fn bool_question(&self, question: &str, default: bool) -> Result<bool>;
+    /// Asks the user for a line of text
+    fn input(&self, question: &str) -> Result<String>;
+    /// Asks the user for a passphrase
+    fn passphrase(&self, question: &str) -> Result<String>;
+}
+
+/// How questions are answered
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Policy {
+    /// Ask the user
+    #[default]
+    Ask,
+    /// Answer yes to every yes/no question
+    Yes,
+    /// Answer no to every yes/no question
+    No,
+    /// Use the default (safe) answer of every yes/no question
+    NonInteractive,
+}
+
+/// Asks on the terminal, unless the policy answers the question
+pub struct Terminal {
+    pub policy: Policy,
+}
+impl Prompt for Terminal {
+    fn bool_question(&self, question: &str, default: bool) -> Result<bool> {
+        let (answer, flag) = match self.policy {
+            Policy::Ask => return ask_bool(question),
+            Policy::Yes => (true, "--yes"),
+            Policy::No => (false, "--no"),
+            Policy::NonInteractive => (default, "--non-interactive"),
+        };
+
+        if !SILENT.get().is_some_and(|silent| *silent) {
+            println!("{question} {} ({flag})", if answer { "yes" } else { "no" });
+        }
+
+        Ok(answer)
+    }
+    fn input(&self, question: &str) -> Result<String> {
+        self.ensure_interactive(question)?;
+
+        println!("{question}:");
+
+        read_line(question).map(|line| line.trim().to_owned())
+    }
+    fn passphrase(&self, question: &str) -> Result<String> {
+        self.ensure_interactive(question)?;
+
+        rpassword::prompt_password(format!("{question} ")).context("reading passphrase")
+    }
+}
+impl Terminal {
+    /// Fails if the question can't be asked
+    fn ensure_interactive(&self, question: &str) -> Result<()> {
+        if self.policy != Policy::Ask {
+            bail!("\"{question}\" can't be answered automatically, run dots interactively instead");
+        }
+
+        Ok(())
+    }
+}
+
+/// Asks the yes/no question on the terminal until it is answered
+fn ask_bool(question: &str) -> Result<bool> {
+    print!("{question} ");
+
+    stdout().flush()?;
+
+    loop {
+        match read_line(question)?.trim() {
+            "y" | "Y" | "yes" | "Yes" => return Ok(true),
+            "n" | "N" | "no" | "No" => return Ok(false),
+            _other => {}
+        }
+    }
+}
+
+/// Reads a line from stdin, failing at the end of the input
+fn read_line(question: &str) -> Result<String> {
+    let mut buffer = String::with_capacity(3); // The longest accepted answer to yes/no questions is 3 characters long
+
+    if stdin()
+        .read_line(&mut buffer)
+        .context("reading from stdin")?
+        == 0
+    {
+        bail!(
+            "Reached the end of the input while waiting for an answer to \"{question}\". Use --yes, --no or --non-interactive to run without a terminal"
+        );
+    }
+
+    Ok(buffer)
+}
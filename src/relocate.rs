@@ -0,0 +1,130 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::{Context as _, Result, ensure};
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::{
+    SILENT,
+    batch::run_all,
+    context::Context,
+    link,
+    list::crawl,
+    ops::{self, dry_run},
+    placeholders::expand_with,
+};
+
+/// Points every symlink below `list_paths` that targets `old` at the same path below `new`,
+/// and sets `files_path` in the config to `new` if it is `old`
+pub fn relocate(context: &Context, old: &str, new: &str) -> Result<()> {
+    let config = &context.config;
+    let old_path = PathBuf::from(expand_with(old, &config.vars)?);
+    let new_path = PathBuf::from(expand_with(new, &config.vars)?);
+
+    ensure!(
+        new_path.is_dir(),
+        "{} doesn't exist, move the files/ directory there first",
+        new_path.display()
+    );
+
+    let relinked: BTreeMap<_, _> = crawl(config)
+        .into_iter()
+        .filter_map(|(link, target)| {
+            let relative = target.strip_prefix(&old_path).ok()?;
+            Some((link, new_path.join(relative)))
+        })
+        .collect();
+
+    let result = if relinked.is_empty() {
+        println!("No symlinks point into {}", old_path.display());
+        Ok(())
+    } else {
+        let links: Vec<_> = relinked.keys().cloned().collect();
+
+        run_all(&links, |link| {
+            let target = relinked.get(link).context("Symlink went missing")?;
+
+            relink(link, target)
+        })
+    };
+
+    // The config is updated even if some symlinks failed, as the files/ directory has moved anyways
+    if Path::new(&config.files_path) == old_path {
+        set_files_path(&config.path, new)?;
+    } else if Path::new(&config.files_path) != new_path
+        && !SILENT.get().is_some_and(|silent| *silent)
+    {
+        eprintln!(
+            "files_path is {}, not {}, so it wasn't changed",
+            config.files_path,
+            old_path.display()
+        );
+    }
+
+    result
+}
+
+/// Atomically points the symlink at the target, by creating a new symlink next to it and moving it over the old one
+fn relink(link: &Path, target: &Path) -> Result<()> {
+    if dry_run() {
+        println!("Would relink {} to {}", link.display(), target.display());
+        return Ok(());
+    }
+
+    println!("Relinking {} to {}", link.display(), target.display());
+
+    let mut temporary = link.as_os_str().to_owned();
+    temporary.push(format!(".dots-relocate-{}", process::id()));
+    let temporary = PathBuf::from(temporary);
+
+    // Keep relative symlinks relative
+    let relative = fs::read_link(link)
+        .with_context(|| format!("reading symlink {}", link.display()))?
+        .is_relative();
+    link::symlink(target, &temporary, relative)
+        .with_context(|| format!("creating symlink {}", temporary.display()))?;
+
+    if let Err(error) = ops::rename(&temporary, link) {
+        // Dont leave the new symlink lying around
+        if let Err(remove_error) = ops::remove(&temporary) {
+            eprintln!("{remove_error:#}");
+        }
+
+        return Err(error).with_context(|| format!("replacing symlink {}", link.display()));
+    }
+
+    Ok(())
+}
+
+/// Sets `files_path` in the config file at the path, keeping the rest of its formatting (like comments)
+fn set_files_path(path: &Path, files_path: &str) -> Result<()> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("reading config at {}", path.display()))?;
+    let mut document = contents
+        .parse::<DocumentMut>()
+        .with_context(|| format!("parsing config at {}", path.display()))?;
+
+    match document.get_mut("files_path").and_then(Item::as_value_mut) {
+        Some(value) => {
+            // Keep the comments around the value
+            let decor = value.decor().clone();
+            *value = Value::from(files_path);
+            *value.decor_mut() = decor;
+        }
+        None => document["files_path"] = toml_edit::value(files_path),
+    }
+
+    if dry_run() {
+        println!("Would set files_path to {files_path} in {}", path.display());
+        return Ok(());
+    }
+
+    ops::write(path, &document.to_string())?;
+    println!("Set files_path to {files_path} in {}", path.display());
+
+    Ok(())
+}
@@ -1,13 +1,115 @@
-use std::{fs::remove_file, path::Path};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context as _, Result};
+use anyhow::{Context as _, Result, anyhow, bail};
 
-use crate::util::system_path;
+use crate::{
+    backups::backup,
+    context::Context,
+    hooks::{HookCommand, with_hooks},
+    link,
+    ops::{self, dry_run},
+    source::Source,
+    util::{config_path, paths_equal, system_path},
+};
 
-pub fn remove(path: &Path) -> Result<()> {
-    let path = system_path(path)?;
+/// What `remove` did
+pub struct Removed {
+    pub system_path: PathBuf,
+    /// The id of the backup of the removed path, if it was forcefully removed
+    pub backup: Option<String>,
+    /// The path the system path was restored from, with --restore
+    pub restored_from: Option<PathBuf>,
+}
+
+/// Removes the given path from the system, if it was made by dots.
+/// Forcefully removed paths are moved into the backup store.
+pub fn remove(
+    context: &Context,
+    path: &Path,
+    copy: bool,
+    force: bool,
+    restore: bool,
+) -> Result<Removed> {
+    with_hooks(&context.config, HookCommand::Remove, path, || {
+        context.escalating(|| remove_path(context, path, copy, force, restore))
+    })
+}
+
+fn remove_path(
+    context: &Context,
+    path: &Path,
+    copy: bool,
+    force: bool,
+    restore: bool,
+) -> Result<Removed> {
+    let config = &context.config;
+    let config_path = config_path(config, path)?;
+    let system_path = system_path(config, path)?;
+
+    fs::symlink_metadata(&system_path)
+        .with_context(|| format!("{} doesn't exist", system_path.display()))?;
+
+    // The path the content should be restored from
+    let mut source = config_path.clone();
+
+    let made_by_dots = if copy {
+        paths_equal(&Source::new(context, &config_path)?.path, &system_path)
+    } else {
+        link::target(&system_path)
+            .with_context(|| format!("{} is not a symlink", system_path.display()))
+            .and_then(|destination| {
+                if destination.starts_with(&config.files_path) {
+                    source = destination;
+                    Ok(())
+                } else {
+                    Err(anyhow!(
+                        "{} points to {}, which is outside of {}",
+                        system_path.display(),
+                        destination.display(),
+                        config.files_path
+                    ))
+                }
+            })
+    };
+
+    let backup = match made_by_dots {
+        Ok(()) => {
+            if !dry_run() {
+                println!("Removing {}", system_path.display());
+            }
+
+            ops::remove(&system_path)?;
+
+            None
+        }
+        Err(e) if force => {
+            eprintln!("{e:#}");
+            Some(backup(&system_path)?)
+        }
+        Err(e) => bail!(e.context(format!(
+            "Refusing to remove {}, as it wasn't made by dots. Use --force to remove it anyway",
+            system_path.display()
+        ))),
+    };
+
+    if restore {
+        if !dry_run() {
+            println!(
+                "Restoring {} to {}",
+                source.display(),
+                system_path.display()
+            );
+        }
 
-    remove_file(path).context("deleting symlink")?;
+        ops::copy(&source, &system_path, false)?;
+    }
 
-    Ok(())
+    Ok(Removed {
+        system_path,
+        backup,
+        restored_from: restore.then_some(source),
+    })
 }
@@ -0,0 +1,131 @@
+//! Encryption of secrets (files in files/ ending in ".dots.enc").
+//!
+//! Files are encrypted with ChaCha20-Poly1305, using a key derived with Argon2id from
+//! the contents of the `key_file` in the config, or a passphrase if there is none.
+
+use std::{
+    fs::{self, File},
+    io::Read as _,
+};
+
+use anyhow::{Context as _, Result, anyhow, ensure};
+use argon2::Argon2;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit as _, Nonce, aead::Aead as _};
+
+use crate::{config::Config, prompt::Prompt};
+
+/// The suffix that marks a file in files/ as encrypted
+pub const SUFFIX: &str = ".dots.enc";
+
+/// Identifies (the version of) the format of encrypted files
+const MAGIC: &[u8; 8] = b"DOTSENC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Encrypts the plaintext with the secret, using a new random salt and nonce
+pub fn encrypt(secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    random(&mut salt)?;
+    random(&mut nonce)?;
+
+    let ciphertext = cipher(secret, &salt)?
+        .encrypt(&Nonce::from(nonce), plaintext)
+        .map_err(|_| anyhow!("Failed to encrypt"))?;
+
+    Ok([MAGIC.as_slice(), &salt, &nonce, &ciphertext].concat())
+}
+
+/// Decrypts the contents of an encrypted file with the secret
+pub fn decrypt(secret: &[u8], encrypted: &[u8]) -> Result<Vec<u8>> {
+    let rest = encrypted
+        .strip_prefix(MAGIC)
+        .context("Not an encrypted dots file")?;
+    ensure!(
+        rest.len() >= SALT_LEN + NONCE_LEN,
+        "Encrypted file is truncated"
+    );
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let nonce: [u8; NONCE_LEN] = nonce.try_into()?;
+
+    cipher(secret, salt)?
+        .decrypt(&Nonce::from(nonce), ciphertext)
+        .map_err(|_| {
+            anyhow!("Failed to decrypt (wrong key or passphrase, or the file was modified)")
+        })
+}
+
+/// The cipher with the key derived from the secret and salt
+fn cipher(secret: &[u8], salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|e| anyhow!("Failed to derive key: {e}"))?;
+
+    Ok(ChaCha20Poly1305::new(&Key::from(key)))
+}
+
+/// The contents of the key file, or the passphrase if there is none
+pub fn read_secret(config: &Config, prompt: &dyn Prompt) -> Result<Vec<u8>> {
+    let secret = match config.key_file {
+        Some(ref key_file) => {
+            fs::read(key_file).with_context(|| format!("reading key file {key_file}"))?
+        }
+        None => prompt
+            .passphrase("Passphrase for encrypted files:")?
+            .into_bytes(),
+    };
+    ensure!(!secret.is_empty(), "The key or passphrase is empty");
+
+    Ok(secret)
+}
+
+/// Fills the buffer with random bytes from the kernel
+fn random(buffer: &mut [u8]) -> Result<()> {
+    File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(buffer))
+        .context("reading random bytes from /dev/urandom")
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{decrypt, encrypt};
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let encrypted = encrypt(b"key", b"password=hunter2\n")?;
+
+        assert!(
+            !encrypted
+                .windows(b"hunter2".len())
+                .any(|window| window == b"hunter2")
+        );
+        assert_eq!(decrypt(b"key", &encrypted)?, b"password=hunter2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn uses_new_salt_and_nonce() -> Result<()> {
+        assert_ne!(encrypt(b"key", b"same")?, encrypt(b"key", b"same")?);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_wrong_keys_and_modified_files() -> Result<()> {
+        let mut encrypted = encrypt(b"key", b"secret")?;
+
+        assert!(decrypt(b"other key", &encrypted).is_err(), "wrong key");
+        assert!(decrypt(b"key", &encrypted[..20]).is_err(), "truncated");
+        assert!(decrypt(b"key", b"not encrypted").is_err(), "not encrypted");
+
+        if let Some(last) = encrypted.last_mut() {
+            *last ^= 1;
+        }
+        assert!(decrypt(b"key", &encrypted).is_err(), "modified");
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    env,
+    fs::{self, OpenOptions, Permissions},
+    io::Write as _,
+    os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{Context as _, Result};
+
+use crate::{
+    context::Context,
+    secret::{self, decrypt},
+    template::{self, render},
+    util::paths_equal_shown,
+};
+
+/// What actually gets put on the system for a config path.
+/// Templates are rendered and secrets decrypted to a temporary file, which is removed again on drop.
+pub struct Source {
+    /// The path in files/ the source was generated from
+    pub origin: PathBuf,
+    /// The path containing the content that should be put on the system
+    pub path: PathBuf,
+    /// Whether the content is a decrypted secret, which shouldn't be shown
+    pub secret: bool,
+    generated: bool,
+}
+impl Source {
+    pub fn new(context: &Context, config_path: &Path) -> Result<Self> {
+        let Some(origin) = generated_from(config_path) else {
+            return Ok(Self::plain(config_path));
+        };
+
+        let (contents, permissions) = if is_secret(&origin) {
+            let encrypted =
+                fs::read(&origin).with_context(|| format!("reading {}", origin.display()))?;
+            let decrypted = decrypt(context.secret()?, &encrypted)
+                .with_context(|| format!("decrypting {}", origin.display()))?;
+
+            // Secrets are only readable by their owner
+            (decrypted, Permissions::from_mode(0o600))
+        } else {
+            let template = fs::read_to_string(&origin)
+                .with_context(|| format!("reading template {}", origin.display()))?;
+            let rendered = render(&template, &context.config.vars)
+                .with_context(|| format!("rendering template {}", origin.display()))?;
+
+            (rendered.into_bytes(), fs::metadata(&origin)?.permissions())
+        };
+
+        let source = Self {
+            secret: is_secret(&origin),
+            origin,
+            path: temp_path(config_path)?,
+            generated: true,
+        };
+
+        // Create the file as private, as it may contain secrets
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&source.path)
+            .and_then(|mut file| file.write_all(&contents))
+            .with_context(|| format!("writing {}", source.path.display()))?;
+        fs::set_permissions(&source.path, permissions)
+            .with_context(|| format!("setting permissions of {}", source.path.display()))?;
+
+        Ok(source)
+    }
+    /// A path that is put on the system as it is
+    pub fn plain(path: &Path) -> Self {
+        Self {
+            origin: path.into(),
+            path: path.into(),
+            secret: false,
+            generated: false,
+        }
+    }
+    /// Checks if the system path is equal to the source, see `paths_equal()`
+    pub fn equals(&self, system_path: &Path) -> Result<()> {
+        paths_equal_shown(&self.path, system_path, &self.origin)
+    }
+}
+impl Drop for Source {
+    fn drop(&mut self) {
+        if self.generated
+            && let Err(e) = fs::remove_file(&self.path)
+        {
+            eprintln!("Failed to remove {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// The file (template or secret) the config path is generated from, if the config path doesn't exist itself.
+/// Generated paths have to be copied instead of symlinked.
+pub fn generated_from(config_path: &Path) -> Option<PathBuf> {
+    if fs::symlink_metadata(config_path).is_ok() {
+        return None;
+    }
+
+    [template::SUFFIX, secret::SUFFIX]
+        .into_iter()
+        .map(|suffix| with_suffix(config_path, suffix))
+        .find(|path| fs::symlink_metadata(path).is_ok())
+}
+
+/// Whether the config path exists, or can be generated
+pub fn exists(config_path: &Path) -> bool {
+    fs::symlink_metadata(config_path).is_ok() || generated_from(config_path).is_some()
+}
+
+/// Appends the suffix to the path
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// The path a template or secret is generated for, or the path itself if it is neither
+pub fn without_suffix(path: &Path) -> PathBuf {
+    path.to_str()
+        .and_then(|path| {
+            path.strip_suffix(template::SUFFIX)
+                .or_else(|| path.strip_suffix(secret::SUFFIX))
+        })
+        .map_or_else(|| path.into(), Into::into)
+}
+
+fn is_secret(origin: &Path) -> bool {
+    origin
+        .to_str()
+        .is_some_and(|origin| origin.ends_with(secret::SUFFIX))
+}
+
+/// A unique temporary path for content generated from the config path
+fn temp_path(config_path: &Path) -> Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = config_path
+        .file_name()
+        .context("Config path has no file name")?
+        .to_str()
+        .context("Failed to convert file name to string")?;
+
+    Ok(env::temp_dir().join(format!(
+        "dots-{}-{}-{file_name}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )))
+}
@@ -0,0 +1,111 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+
+use crate::{
+    context::Context,
+    link, manifest,
+    source::{self, Source},
+    util::{config_path, paths_equal, system_path},
+};
+
+/// The state of a managed path on the system
+pub enum State {
+    /// The symlink exists and points to the config path
+    Linked,
+    /// The copy exists and is equal to the config path
+    Copied,
+    /// Nothing exists at the system path
+    Missing,
+    /// A symlink exists, but points to the contained destination instead of the config path
+    PointsElsewhere(PathBuf),
+    /// A regular file or directory exists instead of the symlink
+    Replaced,
+    /// The copy exists, but differs from the config path
+    Drifted,
+}
+impl State {
+    /// Whether the system path is what `add` would make it
+    #[must_use]
+    pub const fn in_sync(&self) -> bool {
+        matches!(self, Self::Linked | Self::Copied)
+    }
+}
+impl Display for State {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.pad(match *self {
+            Self::Linked => "linked",
+            Self::Copied => "copied",
+            Self::Missing => "missing",
+            Self::PointsElsewhere(_) => "elsewhere",
+            Self::Replaced => "replaced",
+            Self::Drifted => "drifted",
+        })
+    }
+}
+
+/// Determines the state of the system path.
+/// Generated paths (like templates) are always treated as copies.
+pub fn state(
+    context: &Context,
+    config_path: &Path,
+    system_path: &Path,
+    copy: bool,
+) -> Result<State> {
+    let Ok(metadata) = fs::symlink_metadata(system_path) else {
+        return Ok(State::Missing);
+    };
+
+    Ok(if copy || source::generated_from(config_path).is_some() {
+        if paths_equal(&Source::new(context, config_path)?.path, system_path).is_ok() {
+            State::Copied
+        } else {
+            State::Drifted
+        }
+    } else if metadata.is_symlink() {
+        let destination = link::target(system_path).with_context(|| {
+            format!(
+                "reading symlink destination for path {}",
+                system_path.display()
+            )
+        })?;
+
+        if destination == config_path {
+            State::Linked
+        } else {
+            State::PointsElsewhere(destination)
+        }
+    } else {
+        State::Replaced
+    })
+}
+
+/// Prints the state of every path in the manifest, returns whether all of them are in sync
+pub fn status(context: &Context) -> Result<bool> {
+    let mut out_of_sync = false;
+
+    for entry in manifest::load(&context.config)? {
+        let config_path = config_path(&context.config, &entry.path)?;
+        let system_path = system_path(&context.config, &entry.path)?;
+
+        let state = state(context, &config_path, &system_path, entry.copy)?;
+
+        out_of_sync |= !state.in_sync();
+
+        if let State::PointsElsewhere(ref destination) = state {
+            println!(
+                "{state:<10} {} -> {}",
+                entry.path.display(),
+                destination.display()
+            );
+        } else {
+            println!("{state:<10} {}", entry.path.display());
+        }
+    }
+
+    Ok(!out_of_sync)
+}
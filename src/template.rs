@@ -0,0 +1,207 @@
+//! Rendering of templates (files in files/ ending in ".dots.tmpl").
+//!
+//! `{{ name }}` is replaced with the value of the placeholder with that name (see `placeholders`),
+//! `{{#if name}}`, `{{#if name == "value"}}`, `{{#if name != "value"}}`, `{{else}}` and `{{/if}}` can be used for conditionals.
+//! Lines containing only `{{#if ...}}`, `{{else}}` or `{{/if}}` (and whitespace) are removed entirely.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context as _, Result, ensure};
+
+use crate::placeholders::value;
+
+/// The suffix that marks a file in files/ as a template
+pub const SUFFIX: &str = ".dots.tmpl";
+
+/// An `{{#if}}` block
+struct Block {
+    /// Whether the surrounding block is being rendered
+    parent_active: bool,
+    condition: bool,
+    in_else: bool,
+}
+impl Block {
+    const fn active(&self) -> bool {
+        self.parent_active && self.condition != self.in_else
+    }
+}
+
+/// Renders the template, using the given variables for custom placeholders
+pub fn render(template: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut rest = template;
+
+    let active = |blocks: &[Block]| blocks.last().is_none_or(Block::active);
+    // Whether only whitespace precedes `rest` on its line
+    let mut line_start = true;
+
+    while let Some((before, after)) = rest.split_once("{{") {
+        let (tag, after) = after.split_once("}}").context("Unclosed {{ in template")?;
+        let tag = tag.trim();
+
+        // Block tags on their own line are removed along with the line
+        let is_block_tag = tag.starts_with("#if ") || tag == "else" || tag == "/if";
+        let after_on_line = after.trim_start_matches([' ', '\t']);
+        let own_line = match before.rsplit_once('\n') {
+            Some((_, line)) => line.trim().is_empty(),
+            None => line_start && before.trim().is_empty(),
+        } && (after_on_line.is_empty()
+            || after_on_line.starts_with('\n')
+            || after_on_line.starts_with("\r\n"));
+        let standalone = is_block_tag && own_line;
+
+        if active(&blocks) {
+            output.push_str(if standalone {
+                before.trim_end_matches([' ', '\t'])
+            } else {
+                before
+            });
+        }
+
+        if let Some(condition) = tag.strip_prefix("#if ") {
+            let parent_active = active(&blocks);
+            blocks.push(Block {
+                parent_active,
+                condition: parent_active && evaluate(condition.trim(), vars),
+                in_else: false,
+            });
+        } else if tag == "else" {
+            blocks
+                .last_mut()
+                .context("{{else}} without {{#if}} in template")?
+                .in_else = true;
+        } else if tag == "/if" {
+            blocks
+                .pop()
+                .context("{{/if}} without {{#if}} in template")?;
+        } else if active(&blocks) {
+            output.push_str(&value(tag, vars)?);
+        }
+
+        rest = if standalone {
+            after_on_line
+                .strip_prefix("\r\n")
+                .or_else(|| after_on_line.strip_prefix('\n'))
+                .unwrap_or(after_on_line)
+        } else {
+            after
+        };
+        line_start = standalone;
+    }
+
+    if active(&blocks) {
+        output.push_str(rest);
+    }
+
+    ensure!(blocks.is_empty(), "Unclosed {{{{#if}}}} in template");
+
+    Ok(output)
+}
+
+/// Evaluates the condition of an `{{#if}}`.
+/// Placeholders that can't be resolved are treated as empty.
+fn evaluate(condition: &str, vars: &BTreeMap<String, String>) -> bool {
+    let value = |name: &str| value(name.trim(), vars).unwrap_or_default();
+    let literal = |literal: &str| literal.trim().trim_matches('"').to_owned();
+
+    if let Some((name, expected)) = condition.split_once("!=") {
+        value(name) != literal(expected)
+    } else if let Some((name, expected)) = condition.split_once("==") {
+        value(name) == literal(expected)
+    } else {
+        let value = value(condition);
+        !value.is_empty() && value != "false" && value != "0"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use anyhow::Result;
+
+    use super::render;
+
+    fn vars() -> BTreeMap<String, String> {
+        [("theme", "dark"), ("laptop", "true"), ("empty", "")]
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn replaces_placeholders() -> Result<()> {
+        assert_eq!(render("theme = {{ theme }}\n", &vars())?, "theme = dark\n");
+        Ok(())
+    }
+
+    #[test]
+    fn conditions() -> Result<()> {
+        let template = "{{#if theme == \"dark\"}}\nA\n{{else}}\nB\n{{/if}}\n";
+        assert_eq!(render(template, &vars())?, "A\n");
+
+        let template = "{{#if theme != \"dark\"}}\nA\n{{else}}\nB\n{{/if}}\n";
+        assert_eq!(render(template, &vars())?, "B\n");
+
+        // Empty and unknown placeholders are false
+        let template = "{{#if empty}}A{{/if}}{{#if unknown}}B{{/if}}{{#if laptop}}C{{/if}}";
+        assert_eq!(render(template, &vars())?, "C");
+        Ok(())
+    }
+
+    #[test]
+    fn nested_conditions() -> Result<()> {
+        let template = "\
+{{#if laptop}}
+{{#if theme == \"light\"}}
+light laptop
+{{else}}
+dark laptop
+{{/if}}
+{{else}}
+{{#if theme}}
+desktop
+{{/if}}
+{{/if}}
+";
+        assert_eq!(render(template, &vars())?, "dark laptop\n");
+        Ok(())
+    }
+
+    #[test]
+    fn inline_conditions_keep_the_newline() -> Result<()> {
+        let template = "font = {{#if laptop}}small{{else}}big{{/if}}\nsize = 3\n";
+        assert_eq!(render(template, &vars())?, "font = small\nsize = 3\n");
+
+        let template = "{{#if laptop}}a{{/if}}\r\nb";
+        assert_eq!(render(template, &vars())?, "a\r\nb");
+        Ok(())
+    }
+
+    #[test]
+    fn standalone_tags_remove_their_line() -> Result<()> {
+        let template = "a\n  {{#if laptop}}\n  b\n  {{/if}}  \r\nc";
+        assert_eq!(render(template, &vars())?, "a\n  b\nc");
+        Ok(())
+    }
+
+    #[test]
+    fn inactive_blocks_dont_resolve_placeholders() -> Result<()> {
+        assert_eq!(render("{{#if empty}}{{ unknown }}{{/if}}", &vars())?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        for template in [
+            "{{ unknown }}",
+            "{{ theme",
+            "{{#if laptop}}unclosed",
+            "{{else}}",
+            "{{/if}}",
+        ] {
+            assert!(render(template, &vars()).is_err(), "{template}");
+        }
+    }
+}
@@ -1,20 +1,34 @@
 use std::{
-    env::{self, current_exe},
+    collections::BTreeSet,
+    env,
+    ffi::OsString,
     fs::{self, File},
     io::{BufReader, Read as _},
+    os::unix::fs::MetadataExt as _,
     path::{Path, PathBuf},
-    process::{Command, exit},
 };
 
-use anyhow::{Context as _, Result, anyhow};
+use anyhow::{Context as _, Result, anyhow, ensure};
 
-use crate::{SILENT, config::CONFIG};
+use crate::{
+    config::Config,
+    placeholders::{collapse_home, collapse_subdir, expand_with},
+    source,
+};
 
 /// The absolute path to the users home directory.
 pub fn home() -> Result<String> {
     env::var("HOME").context("Failed to get HOME env variable")
 }
 
+/// The XDG base directory in the env variable, or `{home}/{default}` if it isn't set
+pub fn xdg_dir(variable: &str, default: &str) -> Result<PathBuf> {
+    match env::var(variable) {
+        Ok(dir) if !dir.is_empty() => Ok(dir.into()),
+        _ => Ok(PathBuf::from(home()?).join(default)),
+    }
+}
+
 pub fn get_hostname() -> Result<String> {
     Ok(fs::read_to_string("/etc/hostname")
         .context("Failed to read /etc/hostname")?
@@ -22,122 +36,146 @@ pub fn get_hostname() -> Result<String> {
         .into())
 }
 
-/// Inform the user of the `failed_action` and rerun with root privileges
-#[expect(clippy::expect_used)] // We dont return anyways, so we might as well panic
-pub fn rerun_with_root(failed_action: &str) -> ! {
-    if !SILENT.get().expect("Failed to get SILENT") {
-        println!("{failed_action} requires root privileges",);
-    }
-    rerun_with_root_args(&[]);
-}
-
-/// Rerun with root privileges, and add the provided args to the command
-#[expect(clippy::expect_used)] // We dont return anyways, so we might as well panic
-pub fn rerun_with_root_args(args: &[&str]) -> ! {
-    // Collect args
-    let mut args: Vec<_> = env::args()
-        .chain(args.iter().map(|&str| str.to_owned()))
-        .collect();
-
-    // Overwrite the exe path with the absolute path if possible
-    if let Some(absolute_path) = current_exe()
-        .ok()
-        .and_then(|path| path.to_str().map(ToOwned::to_owned))
-    {
-        args[0] = absolute_path;
-    }
-
-    let home = home().expect("Failed to get users home di");
-
-    let status = Command::new("/usr/bin/sudo")
-        // Preserve $HOME
-        .arg(format!("HOME={home}"))
-        .args(args)
-        .spawn()
-        .expect("Failed to spawn child process")
-        .wait()
-        .expect("Failed to wait on child process");
-
-    if status.success() {
-        exit(0);
-    } else {
-        exit(status.code().unwrap_or(1));
-    }
-}
-
 /// Converts the path relative to files/ to the location on the actual system. (by trimming the subdir of files/ away)
-pub fn system_path(path: &Path) -> Result<PathBuf> {
-    let str = path
-        .as_os_str()
-        .to_str()
-        .context("Failed to convert path to string")?;
-
-    // Replace {home} with the users home dir
-    let resolved_home = str.replace("{home}", &home()?[1..]);
-
-    Ok(if path.is_relative() {
-        let index = str
+pub fn system_path(config: &Config, path: &Path) -> Result<PathBuf> {
+    let expanded = expand_with(
+        path.as_os_str()
+            .to_str()
+            .context("Failed to convert path to string")?,
+        &config.vars,
+    )?;
+
+    Ok(if expanded.starts_with('/') {
+        // The default subdir was elided, so the path is already the correct one
+        expanded.into()
+    } else {
+        let index = expanded
             .find('/')
             .with_context(|| format!("Failed finding '/' in path '{}'", path.display()))?;
 
         // Only keep the path from the first /
-        let absolute = &resolved_home[index..];
-
-        absolute.into()
-    } else {
-        // The default subdir was elided, so the path is already the correct one
-        resolved_home.into()
+        expanded[index..].into()
     })
 }
 
 /// Converts the path that should be symlinked to the path in the files/ directory
-#[expect(clippy::literal_string_with_formatting_args)]
-pub fn config_path(mut cli_path: &Path) -> Result<PathBuf> {
-    assert!(
-        !Path::new(&CONFIG.default_subdir).is_absolute(),
+pub fn config_path(config: &Config, cli_path: &Path) -> Result<PathBuf> {
+    Ok(resolve_config_path(config, cli_path)?.path)
+}
+
+/// A path in the files/ directory
+pub struct ConfigPath {
+    pub path: PathBuf,
+    /// The sub-dir of files/ the path is in
+    pub layer: String,
+}
+
+/// Converts the path that should be symlinked to the path in the files/ directory.
+/// If the sub-dir was elided, the layers of the active profile are searched, see `Config::layers()`.
+pub fn resolve_config_path(config: &Config, cli_path: &Path) -> Result<ConfigPath> {
+    ensure!(
+        !Path::new(&config.default_subdir).is_absolute(),
         "Default subdir is not allowed to be absolute"
     );
 
-    let mut config_path = PathBuf::from(&CONFIG.files_path);
+    let expanded = expand_with(
+        cli_path
+            .to_str()
+            .context("Failed to convert path to string")?,
+        &config.vars,
+    )?;
+    let expanded = Path::new(&expanded);
 
     // If the path started with "/", the default subdir was elided
-    if let Ok(relative_path) = cli_path.strip_prefix("/") {
-        // So we add it
-        config_path.push(&CONFIG.default_subdir);
+    let (layer, relative_path) = if let Ok(relative_path) = expanded.strip_prefix("/") {
+        // The layer is determined once the rest of the path is known
+        (None, relative_path)
+    } else {
+        let mut components = expanded.components();
+        let subdir = components
+            .next()
+            .context("Path is empty")?
+            .as_os_str()
+            .to_str()
+            .context("Failed to convert subdir to string")?
+            .to_owned();
+
+        (Some(subdir), components.as_path())
+    };
 
-        // And replace the absolute path with the relative one to avoid overwriting the entire config_path
-        cli_path = relative_path;
-    }
-    // If the default subdir wasn't elided, replace "{hostname}" with the actual hostname
-    else if let Ok(stripped_path) = cli_path.strip_prefix("{hostname}") {
-        let hostname = get_hostname();
-        config_path.push(hostname?.trim());
+    let files_path = Path::new(&config.files_path);
+
+    let layer = match layer {
+        Some(layer) => layer,
+        // Use the highest priority layer that contains the path, or the default subdir if none do
+        None => config
+            .layers()?
+            .into_iter()
+            .rev()
+            .find(|layer| source::exists(&files_path.join(layer).join(relative_path)))
+            .unwrap_or_else(|| config.default_subdir.clone()),
+    };
 
-        cli_path = stripped_path;
-    }
+    Ok(ConfigPath {
+        path: files_path.join(&layer).join(relative_path),
+        layer,
+    })
+}
 
-    // Replace "{home}" with the users home dir
-    if let Ok(stripped_path) = cli_path.strip_prefix("{home}") {
-        let home = home()?;
-        config_path.push(&home[1..]); // skip the leading '/' to avoid overwriting the entire config_path
+/// Converts the path relative to files/ to the path in the format taken by `add`.
+/// The sub-dir is elided if it is the layer the elided path would resolve to.
+pub fn cli_path(config: &Config, relative_config_path: &Path) -> Result<String> {
+    let mut components = relative_config_path.components();
+    let subdir = components
+        .next()
+        .context("Path is empty")?
+        .as_os_str()
+        .to_str()
+        .context("Failed to convert subdir to string")?;
 
-        cli_path = stripped_path;
+    let rest = collapse_home(components.as_path())?;
+    let rest = rest.to_str().context("Failed to convert path to string")?;
+
+    let elided = format!("/{rest}");
+    if resolve_config_path(config, Path::new(&elided))?.path
+        == Path::new(&config.files_path).join(relative_config_path)
+    {
+        return Ok(elided);
     }
 
-    config_path.push(cli_path);
+    Ok(format!("{}/{rest}", collapse_subdir(subdir)))
+}
 
-    Ok(config_path)
+/// The effective user id of the current process
+pub fn uid() -> Result<u32> {
+    // /proc/self is owned by the effective user of the process
+    Ok(fs::metadata("/proc/self")
+        .context("Failed to get metadata of /proc/self")?
+        .uid())
 }
 
-/// Checks if the config & system paths are already equal
-/// Does *not* currently support directories
-#[expect(clippy::filetype_is_file)]
+/// Whether the current user is root
+pub fn is_root() -> Result<bool> {
+    Ok(uid()? == 0)
+}
+
+/// Checks if the config & system paths are already equal, recursing into directories
 pub fn paths_equal(config_path: &Path, system_path: &Path) -> Result<()> {
+    paths_equal_shown(config_path, system_path, config_path)
+}
+
+/// Like `paths_equal()`, but naming the config path `shown_config_path` in the error (like a template instead of its rendered version)
+#[expect(clippy::filetype_is_file)]
+pub fn paths_equal_shown(
+    config_path: &Path,
+    system_path: &Path,
+    shown_config_path: &Path,
+) -> Result<()> {
     let fmt_diff = |difference: &str| {
         Err(anyhow!(
             "Path {} already exists and differs in {difference} to {}",
             system_path.display(),
-            config_path.display()
+            shown_config_path.display()
         ))
     };
 
@@ -157,10 +195,42 @@ pub fn paths_equal(config_path: &Path, system_path: &Path) -> Result<()> {
 
     if system_metadata.file_type() != config_metadata.file_type() {
         fmt_diff("file type")
-    } else if system_metadata.len() != config_metadata.len() {
-        fmt_diff("length")
     } else if system_metadata.permissions() != config_metadata.permissions() {
         fmt_diff("permissions")
+    } else if system_metadata.is_dir() {
+        // Special files cant be copied, so they are ignored
+        let entries = |path: &Path| -> Result<BTreeSet<OsString>> {
+            let mut entries = BTreeSet::new();
+            for entry in fs::read_dir(path)
+                .with_context(|| format!("reading directory {}", path.display()))?
+            {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+
+                if file_type.is_file() || file_type.is_dir() || file_type.is_symlink() {
+                    entries.insert(entry.file_name());
+                }
+            }
+            Ok(entries)
+        };
+
+        let config_entries = entries(config_path)?;
+
+        if entries(system_path)? != config_entries {
+            return fmt_diff("directory entries");
+        }
+
+        for name in config_entries {
+            paths_equal_shown(
+                &config_path.join(&name),
+                &system_path.join(&name),
+                &shown_config_path.join(&name),
+            )?;
+        }
+
+        Ok(())
+    } else if system_metadata.len() != config_metadata.len() {
+        fmt_diff("length")
         // If they are symlinks
     } else if system_metadata.file_type().is_symlink()
     // And their destinations dont match